@@ -0,0 +1,145 @@
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use web3::types::{H160, H256};
+
+const ADDRESS_BOOK_DIR: &str = "deployments";
+
+// one deployed contract
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Deployment {
+    pub(crate) address: H160,
+    pub(crate) abi_path: String,
+    pub(crate) transaction_hash: H256,
+    pub(crate) block_number: Option<u64>,
+    pub(crate) deployer: H160,
+}
+
+// contracts deployed on one network by name, kept in a json file
+pub(crate) struct AddressBook {
+    path: PathBuf,
+    deployments: BTreeMap<String, Deployment>,
+    // names recorded since the book was opened
+    recorded: BTreeSet<String>,
+}
+
+// deployments of running tasks by name, recorded once the tasks are done
+pub(crate) type Deployed = Arc<Mutex<Vec<(String, Deployment)>>>;
+
+// deployments/<network>.json, deployments/chain-<id>.json without --network
+pub(crate) fn address_book_path(
+    address_book: Option<PathBuf>,
+    network: Option<&str>,
+    chain_id: u64,
+) -> PathBuf {
+    match (address_book, network) {
+        (Some(path), _) => path,
+        (None, Some(network)) => Path::new(ADDRESS_BOOK_DIR).join(format!("{}.json", network)),
+        (None, None) => Path::new(ADDRESS_BOOK_DIR).join(format!("chain-{}.json", chain_id)),
+    }
+}
+
+// "erc20" for "abi/erc20.abi", the address book name of a deploy without one
+pub(crate) fn file_stem(path: &str) -> String {
+    PathBuf::from(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+impl AddressBook {
+    // an empty book when the file doesn't exist yet
+    pub(crate) async fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let deployments = if path.exists() {
+            serde_json::from_slice(&tokio::fs::read(&path).await?)?
+        } else {
+            BTreeMap::new()
+        };
+
+        Ok(AddressBook {
+            path,
+            deployments,
+            recorded: BTreeSet::new(),
+        })
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // add the deployments of finished tasks and write the file, a failed write is reported
+    // without failing the deploys, returns whether the file was written
+    pub(crate) async fn record(&mut self, deployed: &Deployed) -> bool {
+        let deployed = std::mem::take(&mut *deployed.lock().unwrap());
+        if deployed.is_empty() {
+            return false;
+        }
+        for (name, deployment) in deployed {
+            let name = self.unique_name(name, &deployment);
+            self.deployments.insert(name.clone(), deployment);
+            self.recorded.insert(name);
+        }
+
+        match self.write().await {
+            Ok(()) => true,
+            Err(e) => {
+                println!("write address book {:?} failed: {}", self.path, e);
+                false
+            }
+        }
+    }
+
+    // a name recorded before gets the first free "-2", "-3".. suffix, one of an earlier run is
+    // replaced
+    fn unique_name(&self, name: String, deployment: &Deployment) -> String {
+        if self.recorded.contains(&name) {
+            let unique = (2..)
+                .map(|n| format!("{}-{}", name, n))
+                .find(|unique| !self.recorded.contains(unique))
+                .unwrap();
+            println!(
+                "address book: {} is taken, {:?} recorded as {}",
+                name, deployment.address, unique
+            );
+            return unique;
+        }
+        if let Some(earlier) = self.deployments.get(&name) {
+            println!(
+                "address book: {} moves from {:?} to {:?}",
+                name, earlier.address, deployment.address
+            );
+        }
+
+        name
+    }
+
+    // through a temporary file, so an interrupted write leaves the last book
+    async fn write(&self) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(&self.deployments)?).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+
+        Ok(())
+    }
+
+    // the address of a `@name` reference, other values are returned as they are
+    pub(crate) fn resolve(&self, contract_addr: &str) -> anyhow::Result<String> {
+        let name = match contract_addr.strip_prefix('@') {
+            Some(name) => name,
+            None => return Ok(contract_addr.to_string()),
+        };
+
+        match self.deployments.get(name) {
+            Some(deployment) => Ok(format!("{:?}", deployment.address)),
+            None => bail!("no contract {} in address book {:?}", name, self.path),
+        }
+    }
+}
@@ -1,12 +1,13 @@
-use anyhow::bail;
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use crate::{endpoints::Balance, multi_tasks::Warmup, rate_limit::RateLimits, retry::RetryPolicy};
+use anyhow::{anyhow, bail};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{convert::TryFrom, path::PathBuf, str::FromStr};
 use structopt::StructOpt;
 use tokio::fs;
 use web3::{
     contract::tokens::Tokenizable,
     ethabi::{Int, Token, Uint},
-    types::{Address, H160, H256, U128, U256},
+    types::{AccessList, Address, BlockNumber, H160, H256, U128, U256},
 };
 
 #[derive(StructOpt, Debug)]
@@ -16,6 +17,86 @@ pub(crate) struct Opt {
     #[structopt(name = "version", short = "v")]
     pub(crate) version: bool,
 
+    /// chain ids allowed to receive state-changing transactions, the chain id of the --network is
+    /// allowed too
+    #[structopt(
+        name = "allowed chain id",
+        long = "allow-chain-id",
+        env = "PLATFORM_TOOL_ALLOWED_CHAIN_IDS",
+        use_delimiter = true,
+        global = true
+    )]
+    pub(crate) allowed_chain_ids: Vec<u64>,
+
+    /// send state-changing transactions even if the chain id is not allowed
+    #[structopt(
+        name = "i know this is mainnet",
+        long = "i-know-this-is-mainnet",
+        global = true
+    )]
+    pub(crate) i_know_this_is_mainnet: bool,
+
+    /// named network of networks.toml with the rpc urls, chain id, gas prices and keys
+    #[structopt(
+        name = "network",
+        long = "network",
+        env = "PLATFORM_TOOL_NETWORK",
+        global = true
+    )]
+    pub(crate) network: Option<String>,
+
+    /// address book of deployed contracts, deployments/<network>.json if not set
+    #[structopt(
+        name = "address book",
+        long = "address-book",
+        env = "PLATFORM_TOOL_ADDRESS_BOOK",
+        parse(from_os_str),
+        global = true
+    )]
+    pub(crate) address_book: Option<PathBuf>,
+
+    /// extra header of every rpc request as "Name: value", e.g. an API key
+    #[structopt(
+        name = "rpc header",
+        long = "rpc-header",
+        number_of_values = 1,
+        global = true
+    )]
+    pub(crate) rpc_headers: Vec<String>,
+
+    /// basic auth of the rpc as user:password, credentials in the rpc url work too
+    #[structopt(
+        name = "rpc basic auth",
+        long = "rpc-basic-auth",
+        env = "PLATFORM_TOOL_RPC_BASIC_AUTH",
+        hide_env_values = true,
+        global = true
+    )]
+    pub(crate) rpc_basic_auth: Option<String>,
+
+    /// bearer token sent in the Authorization header of every rpc request
+    #[structopt(
+        name = "rpc bearer token",
+        long = "rpc-bearer-token",
+        env = "PLATFORM_TOOL_RPC_BEARER_TOKEN",
+        hide_env_values = true,
+        global = true
+    )]
+    pub(crate) rpc_bearer_token: Option<String>,
+
+    /// PEM CA certificate trusted for https rpc urls
+    #[structopt(
+        name = "rpc ca cert",
+        long = "rpc-ca-cert",
+        parse(from_os_str),
+        global = true
+    )]
+    pub(crate) rpc_ca_cert: Option<PathBuf>,
+
+    /// accept any TLS certificate of the rpc, for self-signed devnets
+    #[structopt(name = "rpc insecure", long = "rpc-insecure", global = true)]
+    pub(crate) rpc_insecure: bool,
+
     // Note that we mark a field as a subcommand
     #[structopt(subcommand)]
     pub(crate) cmd: Option<Command>,
@@ -26,12 +107,22 @@ pub(crate) struct Opt {
 pub(crate) enum Command {
     Account(Account),
     Contract(Contr),
+    Sign(Sign),
+    Tx(Tx),
+    Block(Block),
+    Scenario(Scenario),
 }
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "account manage")]
 pub(crate) enum Account {
     Getbalance(GetBalance),
+    Transfer(Transfer),
+    Import(Import),
+    Export(Export),
+    New(NewKey),
+    Address(KeyAddress),
+    Derive(Derive),
 }
 
 #[derive(StructOpt, Debug)]
@@ -40,14 +131,52 @@ pub(crate) enum Contr {
     Deploy(Deploy),
     Call(Call),
     Query(Query),
+    Logs(Logs),
+    Watch(Watch),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "message signing")]
+pub(crate) enum Sign {
+    Message(SignMessage),
+    TypedData(SignTypedData),
+    Recover(Recover),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "transaction inspection")]
+pub(crate) enum Tx {
+    Get(TxGet),
+    Receipt(TxReceipt),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "block inspection")]
+pub(crate) enum Block {
+    Get(BlockGet),
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "multi-step scenarios")]
+pub(crate) enum Scenario {
+    Run(ScenarioRun),
 }
 
 #[derive(StructOpt, Debug, Clone)]
 #[structopt(about = "contract deploy")]
 pub(crate) struct Deploy {
-    /// http rpc url
-    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
-    pub(crate) rpc_url: String,
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, repeat it to spread tasks over endpoints
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url", number_of_values = 1)]
+    pub(crate) rpc_url: Vec<String>,
+
+    /// file with one rpc url per line, optionally followed by a weight and a rate limit
+    #[structopt(name = "rpc list", long = "rpc-list", parse(from_os_str))]
+    pub(crate) rpc_list: Option<PathBuf>,
+
+    /// how tasks are spread over the rpc urls: round-robin, least-in-flight or weighted, later
+    /// tasks skip a url with connection errors for a while but a failed task is not resent
+    #[structopt(name = "balance", long = "balance", default_value = "round-robin")]
+    pub(crate) balance: Balance,
 
     /// config file path
     #[structopt(name = "config file", short = "g", long = "config", parse(from_os_str))]
@@ -59,14 +188,91 @@ pub(crate) struct Deploy {
     /// max concurrent tasks
     #[structopt(name = "max concurrent tasks", short = "m", long = "max-multi")]
     pub(crate) _max_concurrent: Option<u32>,
+
+    /// multiplier applied to estimated gas when gas is "auto", 1.2 if the network has none
+    #[structopt(
+        name = "gas multiplier",
+        long = "gas-multiplier",
+        parse(try_from_str = parse_gas_multiplier)
+    )]
+    pub(crate) gas_multiplier: Option<f64>,
+
+    /// transaction type: legacy, 2930 or 1559, legacy if the network has none
+    #[structopt(name = "tx type", long = "tx-type")]
+    pub(crate) tx_type: Option<TxType>,
+
+    /// abort if the worst-case spend of the run exceeds this amount, with unit suffix
+    #[structopt(name = "max spend", long = "max-spend", parse(try_from_str = parse_amount))]
+    pub(crate) max_spend: Option<U256>,
+
+    #[structopt(flatten)]
+    pub(crate) retry: RetryPolicy,
+
+    #[structopt(flatten)]
+    pub(crate) limits: RateLimits,
+
+    #[structopt(flatten)]
+    pub(crate) warmup: Warmup,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "run the deploy, call, query, transfer and assert steps of a scenario file")]
+pub(crate) struct ScenarioRun {
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, repeat it to spread tasks over endpoints
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url", number_of_values = 1)]
+    pub(crate) rpc_url: Vec<String>,
+
+    /// file with one rpc url per line, optionally followed by a weight and a rate limit
+    #[structopt(name = "rpc list", long = "rpc-list", parse(from_os_str))]
+    pub(crate) rpc_list: Option<PathBuf>,
+
+    /// how tasks are spread over the rpc urls: round-robin, least-in-flight or weighted, later
+    /// tasks skip a url with connection errors for a while but a failed task is not resent
+    #[structopt(name = "balance", long = "balance", default_value = "round-robin")]
+    pub(crate) balance: Balance,
+
+    /// scenario yaml file, later steps may use ${steps.<name>.<output>} of earlier ones
+    #[structopt(name = "scenario file", parse(from_os_str))]
+    pub(crate) scenario: PathBuf,
+
+    /// multiplier applied to estimated gas when gas is "auto", 1.2 if the network has none
+    #[structopt(
+        name = "gas multiplier",
+        long = "gas-multiplier",
+        parse(try_from_str = parse_gas_multiplier)
+    )]
+    pub(crate) gas_multiplier: Option<f64>,
+
+    /// transaction type: legacy, 2930 or 1559, legacy if the network has none
+    #[structopt(name = "tx type", long = "tx-type")]
+    pub(crate) tx_type: Option<TxType>,
+
+    /// abort a step if its worst-case spend exceeds this amount, with unit suffix
+    #[structopt(name = "max spend", long = "max-spend", parse(try_from_str = parse_amount))]
+    pub(crate) max_spend: Option<U256>,
+
+    #[structopt(flatten)]
+    pub(crate) retry: RetryPolicy,
+
+    #[structopt(flatten)]
+    pub(crate) limits: RateLimits,
 }
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "contract call")]
 pub(crate) struct Call {
-    /// http rpc url
-    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
-    pub(crate) rpc_url: String,
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, repeat it to spread tasks over endpoints
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url", number_of_values = 1)]
+    pub(crate) rpc_url: Vec<String>,
+
+    /// file with one rpc url per line, optionally followed by a weight and a rate limit
+    #[structopt(name = "rpc list", long = "rpc-list", parse(from_os_str))]
+    pub(crate) rpc_list: Option<PathBuf>,
+
+    /// how tasks are spread over the rpc urls: round-robin, least-in-flight or weighted, later
+    /// tasks skip a url with connection errors for a while but a failed task is not resent
+    #[structopt(name = "balance", long = "balance", default_value = "round-robin")]
+    pub(crate) balance: Balance,
 
     /// config file path
     #[structopt(name = "config file", short = "g", long = "config", parse(from_os_str))]
@@ -78,14 +284,43 @@ pub(crate) struct Call {
     /// max concurrent tasks
     #[structopt(name = "max concurrent tasks", short = "m", long = "max-multi")]
     pub(crate) _max_concurrent: Option<u32>,
+
+    /// multiplier applied to estimated gas when gas is "auto", 1.2 if the network has none
+    #[structopt(
+        name = "gas multiplier",
+        long = "gas-multiplier",
+        parse(try_from_str = parse_gas_multiplier)
+    )]
+    pub(crate) gas_multiplier: Option<f64>,
+
+    /// transaction type: legacy, 2930 or 1559, legacy if the network has none
+    #[structopt(name = "tx type", long = "tx-type")]
+    pub(crate) tx_type: Option<TxType>,
+
+    /// abort if the worst-case spend of the run exceeds this amount, with unit suffix
+    #[structopt(name = "max spend", long = "max-spend", parse(try_from_str = parse_amount))]
+    pub(crate) max_spend: Option<U256>,
+
+    #[structopt(flatten)]
+    pub(crate) retry: RetryPolicy,
+
+    #[structopt(flatten)]
+    pub(crate) limits: RateLimits,
+
+    #[structopt(flatten)]
+    pub(crate) warmup: Warmup,
+
+    /// wait for each call to be mined and print the events it emitted
+    #[structopt(name = "decode logs", long = "decode-logs")]
+    pub(crate) decode_logs: bool,
 }
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "contract query")]
 pub(crate) struct Query {
-    /// http rpc url
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, the --network one if not set
     #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
-    pub(crate) rpc_url: String,
+    pub(crate) rpc_url: Option<String>,
 
     /// config file path
     #[structopt(name = "config file", short = "g", long = "config", parse(from_os_str))]
@@ -103,21 +338,483 @@ pub(crate) struct Query {
 #[derive(StructOpt, Debug)]
 #[structopt(about = "get balance")]
 pub(crate) struct GetBalance {
-    /// http rpc url
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, the --network one if not set
     #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
-    pub(crate) rpc_url: String,
+    pub(crate) rpc_url: Option<String>,
 
     #[structopt(name = "account")]
     pub(crate) account: String,
 }
 
+#[derive(StructOpt, Debug)]
+#[structopt(about = "decoded contract events in a block range as json lines")]
+pub(crate) struct Logs {
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, the --network one if not set
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
+    pub(crate) rpc_url: Option<String>,
+
+    /// config file with contract_addr and abi_path, like the query config
+    #[structopt(name = "config file", short = "g", long = "config", parse(from_os_str))]
+    pub(crate) config: PathBuf,
+
+    /// only logs of this event, all events of the contract by default
+    #[structopt(name = "event", long = "event")]
+    pub(crate) event: Option<String>,
+
+    /// first block, number, latest or earliest
+    #[structopt(
+        name = "from block",
+        long = "from-block",
+        default_value = "latest",
+        parse(try_from_str = parse_block_number)
+    )]
+    pub(crate) from_block: BlockNumber,
+
+    /// last block, number, latest or earliest
+    #[structopt(
+        name = "to block",
+        long = "to-block",
+        default_value = "latest",
+        parse(try_from_str = parse_block_number)
+    )]
+    pub(crate) to_block: BlockNumber,
+
+    /// topic filter <1|2|3|indexed parameter name>=<value>[,value], repeatable
+    #[structopt(name = "topic", long = "topic", number_of_values = 1)]
+    pub(crate) topics: Vec<String>,
+
+    /// write the json lines into this file instead of printing them
+    #[structopt(name = "out", long = "out", parse(from_os_str))]
+    pub(crate) out: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "print decoded contract events as new blocks arrive")]
+pub(crate) struct Watch {
+    /// rpc url, ws(s):// and IPC subscribe to new heads, http(s):// polls, the --network one if not set
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
+    pub(crate) rpc_url: Option<String>,
+
+    /// config file with contract_addr and abi_path, like the query config
+    #[structopt(name = "config file", short = "g", long = "config", parse(from_os_str))]
+    pub(crate) config: PathBuf,
+
+    /// event to watch
+    #[structopt(name = "event", long = "event")]
+    pub(crate) event: String,
+
+    /// topic filter <1|2|3|indexed parameter name>=<value>[,value], repeatable
+    #[structopt(name = "topic", long = "topic", number_of_values = 1)]
+    pub(crate) topics: Vec<String>,
+
+    /// first block when there is no saved height, the current head by default
+    #[structopt(name = "from block", long = "from-block")]
+    pub(crate) from_block: Option<u64>,
+
+    /// file keeping the last processed block, watching resumes from it
+    #[structopt(name = "state", long = "state", parse(from_os_str))]
+    pub(crate) state: Option<PathBuf>,
+
+    /// milliseconds between polls over http
+    #[structopt(name = "poll interval", long = "poll-interval", default_value = "1000")]
+    pub(crate) poll_interval: u64,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "native token transfer")]
+pub(crate) struct Transfer {
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, repeat it to spread tasks over endpoints
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url", number_of_values = 1)]
+    pub(crate) rpc_url: Vec<String>,
+
+    /// file with one rpc url per line, optionally followed by a weight and a rate limit
+    #[structopt(name = "rpc list", long = "rpc-list", parse(from_os_str))]
+    pub(crate) rpc_list: Option<PathBuf>,
+
+    /// how tasks are spread over the rpc urls: round-robin, least-in-flight or weighted, later
+    /// tasks skip a url with connection errors for a while but a failed task is not resent
+    #[structopt(name = "balance", long = "balance", default_value = "round-robin")]
+    pub(crate) balance: Balance,
+
+    /// transfer workload config file path, replaces the single transfer arguments
+    #[structopt(
+        name = "config file",
+        short = "g",
+        long = "config",
+        parse(from_os_str),
+        conflicts_with_all = &["from key", "to", "amount"]
+    )]
+    pub(crate) config: Option<PathBuf>,
+
+    /// sender secret key: hex, env:VAR, file:path, keystore:path, mnemonic:phrase or remote:url#address
+    #[structopt(name = "from key", long = "from-key", required_unless = "config file")]
+    pub(crate) from_key: Option<String>,
+
+    /// receiver address
+    #[structopt(name = "to", long = "to", required_unless = "config file")]
+    pub(crate) to: Option<String>,
+
+    /// amount with unit suffix wei, gwei or FRA (default wei), e.g. 1.5FRA
+    #[structopt(
+        name = "amount",
+        long = "amount",
+        required_unless = "config file",
+        parse(try_from_str = parse_amount)
+    )]
+    pub(crate) amount: Option<U256>,
+
+    /// gas limit or "auto", 21000 if not set
+    #[structopt(name = "gas", long = "gas", parse(try_from_str = parse_gas_limit))]
+    pub(crate) gas: Option<GasValue>,
+
+    /// gas price with unit suffix or "auto", fetched from the node if not set
+    #[structopt(name = "gas price", long = "gas-price")]
+    pub(crate) gas_price: Option<GasValue>,
+
+    /// max fee per gas with unit suffix or "auto", used by 1559 transactions
+    #[structopt(name = "max fee per gas", long = "max-fee-per-gas")]
+    pub(crate) max_fee_per_gas: Option<GasValue>,
+
+    /// max priority fee per gas with unit suffix or "auto", used by 1559 transactions, 0 if unset
+    #[structopt(name = "max priority fee per gas", long = "max-priority-fee-per-gas")]
+    pub(crate) max_priority_fee_per_gas: Option<GasValue>,
+
+    /// multiplier applied to estimated gas when gas is "auto", 1.2 if the network has none
+    #[structopt(
+        name = "gas multiplier",
+        long = "gas-multiplier",
+        parse(try_from_str = parse_gas_multiplier)
+    )]
+    pub(crate) gas_multiplier: Option<f64>,
+
+    /// transaction type: legacy, 2930 or 1559, legacy if the network has none
+    #[structopt(name = "tx type", long = "tx-type")]
+    pub(crate) tx_type: Option<TxType>,
+
+    /// abort if the worst-case spend of the run exceeds this amount, with unit suffix
+    #[structopt(name = "max spend", long = "max-spend", parse(try_from_str = parse_amount))]
+    pub(crate) max_spend: Option<U256>,
+
+    #[structopt(flatten)]
+    pub(crate) retry: RetryPolicy,
+
+    #[structopt(flatten)]
+    pub(crate) limits: RateLimits,
+
+    #[structopt(flatten)]
+    pub(crate) warmup: Warmup,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "import a secret key into a keystore file")]
+pub(crate) struct Import {
+    /// secret key: hex, env:VAR, file:path or keystore:path
+    #[structopt(name = "key", long = "key")]
+    pub(crate) key: String,
+
+    /// keystore file to write
+    #[structopt(name = "keystore", long = "keystore", parse(from_os_str))]
+    pub(crate) keystore: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "export the plaintext secret key")]
+pub(crate) struct Export {
+    /// secret key: keystore:path, env:VAR, file:path or hex
+    #[structopt(name = "key", long = "key")]
+    pub(crate) key: String,
+
+    /// write the secret key into this file instead of printing it
+    #[structopt(name = "out", long = "out", parse(from_os_str))]
+    pub(crate) out: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "generate a new secret key")]
+pub(crate) struct NewKey {
+    /// write the key into this keystore file instead of printing it
+    #[structopt(name = "keystore", long = "keystore", parse(from_os_str))]
+    pub(crate) keystore: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "derive the address of a secret key")]
+pub(crate) struct KeyAddress {
+    /// secret key: hex, env:VAR, file:path or keystore:path
+    #[structopt(name = "key", long = "key")]
+    pub(crate) key: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "derive keys m/44'/60'/0'/0/i from a mnemonic")]
+pub(crate) struct Derive {
+    /// mnemonic phrase, env:VAR or file:path
+    #[structopt(name = "mnemonic", long = "mnemonic")]
+    pub(crate) mnemonic: String,
+
+    /// first index to derive
+    #[structopt(name = "start", long = "start", default_value = "0")]
+    pub(crate) start: u32,
+
+    /// number of keys to derive
+    #[structopt(name = "count", short = "c", long = "count", default_value = "1")]
+    pub(crate) count: u32,
+
+    /// print the secret keys too
+    #[structopt(name = "show keys", long = "show-keys")]
+    pub(crate) show_keys: bool,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "sign a message with EIP-191 personal_sign")]
+pub(crate) struct SignMessage {
+    /// secret key: hex, env:VAR, file:path, keystore:path or mnemonic:phrase
+    #[structopt(name = "key", long = "key")]
+    pub(crate) key: String,
+
+    /// message text, or 0x prefixed hex for raw bytes
+    #[structopt(name = "message", long = "message")]
+    pub(crate) message: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "sign EIP-712 typed data")]
+pub(crate) struct SignTypedData {
+    /// secret key: hex, env:VAR, file:path, keystore:path or mnemonic:phrase
+    #[structopt(name = "key", long = "key")]
+    pub(crate) key: String,
+
+    /// typed data json file with types, primaryType, domain and message
+    #[structopt(name = "typed data", parse(from_os_str))]
+    pub(crate) typed_data: PathBuf,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "recover the signer address of a signature")]
+pub(crate) struct Recover {
+    /// signed message text, or 0x prefixed hex for raw bytes
+    #[structopt(
+        name = "message",
+        long = "message",
+        required_unless = "typed data",
+        conflicts_with = "typed data"
+    )]
+    pub(crate) message: Option<String>,
+
+    /// signed EIP-712 typed data json file
+    #[structopt(name = "typed data", long = "typed-data", parse(from_os_str))]
+    pub(crate) typed_data: Option<PathBuf>,
+
+    /// 65 bytes hex signature
+    #[structopt(name = "signature", long = "signature")]
+    pub(crate) signature: String,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "get a transaction by hash")]
+pub(crate) struct TxGet {
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, the --network one if not set
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
+    pub(crate) rpc_url: Option<String>,
+
+    /// transaction hash
+    #[structopt(name = "hash")]
+    pub(crate) hash: H256,
+
+    /// print json instead of the pretty output
+    #[structopt(name = "json", long = "json")]
+    pub(crate) json: bool,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "get a transaction receipt by hash")]
+pub(crate) struct TxReceipt {
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, the --network one if not set
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
+    pub(crate) rpc_url: Option<String>,
+
+    /// transaction hash
+    #[structopt(name = "hash")]
+    pub(crate) hash: H256,
+
+    /// contract abi file to decode the logs with
+    #[structopt(name = "abi", long = "abi", parse(from_os_str))]
+    pub(crate) abi: Option<PathBuf>,
+
+    /// print json instead of the pretty output
+    #[structopt(name = "json", long = "json")]
+    pub(crate) json: bool,
+}
+
+#[derive(StructOpt, Debug)]
+#[structopt(about = "get a block by number")]
+pub(crate) struct BlockGet {
+    /// rpc url: http(s)://, ws(s):// or an IPC socket path, the --network one if not set
+    #[structopt(name = "rpc url", short = "u", long = "rpc-url")]
+    pub(crate) rpc_url: Option<String>,
+
+    /// block number, hex number, latest, pending or earliest
+    #[structopt(name = "number", parse(try_from_str = parse_block_number))]
+    pub(crate) number: BlockNumber,
+
+    /// include the full transactions instead of their hashes
+    #[structopt(name = "full", long = "full")]
+    pub(crate) full: bool,
+
+    /// print json instead of the pretty output
+    #[structopt(name = "json", long = "json")]
+    pub(crate) json: bool,
+}
+
+// transaction envelope type
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(try_from = "String")]
+pub(crate) enum TxType {
+    Legacy,
+    AccessList,
+    DynamicFee,
+}
+
+impl Default for TxType {
+    fn default() -> Self {
+        TxType::Legacy
+    }
+}
+
+impl FromStr for TxType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "legacy" | "0" => Ok(TxType::Legacy),
+            "2930" | "1" => Ok(TxType::AccessList),
+            "1559" | "2" => Ok(TxType::DynamicFee),
+            _ => bail!(
+                "unknown transaction type {}, expect legacy, 2930 or 1559",
+                s
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for TxType {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> anyhow::Result<Self> {
+        s.parse()
+    }
+}
+
+// gas limit or gas price of a transaction, "auto" means asking the node, prices take a unit
+// suffix and limits are plain numbers
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "GasValueJson", into = "GasValueJson")]
+pub(crate) enum GasValue {
+    Auto,
+    Fixed(U256),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum GasValueJson {
+    Num(u64),
+    Str(String),
+}
+
+impl FromStr for GasValue {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        if s.trim() == "auto" {
+            Ok(GasValue::Auto)
+        } else {
+            Ok(GasValue::Fixed(parse_amount(s)?))
+        }
+    }
+}
+
+// gas limit as a whole number or "auto", a unit suffix is a mistake for a price
+pub(crate) fn parse_gas_limit(s: &str) -> anyhow::Result<GasValue> {
+    match s.trim() {
+        "auto" => Ok(GasValue::Auto),
+        gas => match gas.parse::<u64>() {
+            Ok(gas) => Ok(GasValue::Fixed(gas.into())),
+            Err(_) => bail!("gas limit {} should be a whole number or auto", s),
+        },
+    }
+}
+
+fn gas_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<GasValue>, D::Error> {
+    match Option::<GasValueJson>::deserialize(deserializer)? {
+        Some(GasValueJson::Num(n)) => Ok(Some(GasValue::Fixed(n.into()))),
+        Some(GasValueJson::Str(s)) => parse_gas_limit(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+// multiplier of an estimated gas limit, below 1 the transactions run out of gas
+pub(crate) fn parse_gas_multiplier(s: &str) -> anyhow::Result<f64> {
+    let multiplier = s
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("gas multiplier {} should be a number", s))?;
+    check_gas_multiplier(multiplier)?;
+
+    Ok(multiplier)
+}
+
+pub(crate) fn check_gas_multiplier(multiplier: f64) -> anyhow::Result<()> {
+    if !multiplier.is_finite() || multiplier < 1.0 {
+        bail!("gas multiplier {} should be 1 or more", multiplier);
+    }
+
+    Ok(())
+}
+
+impl TryFrom<GasValueJson> for GasValue {
+    type Error = anyhow::Error;
+
+    fn try_from(v: GasValueJson) -> anyhow::Result<Self> {
+        match v {
+            GasValueJson::Num(n) => Ok(GasValue::Fixed(n.into())),
+            GasValueJson::Str(s) => s.parse(),
+        }
+    }
+}
+
+impl From<GasValue> for GasValueJson {
+    fn from(v: GasValue) -> Self {
+        match v {
+            GasValue::Auto => GasValueJson::Str("auto".to_string()),
+            GasValue::Fixed(n) => GasValueJson::Str(n.to_string()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct DeployJsonObj {
+    // address book name, the abi file name if not set
+    #[serde(default)]
+    pub(crate) name: Option<String>,
     pub(crate) code_path: String,
     pub(crate) abi_path: String,
     pub(crate) sec_key: String,
-    pub(crate) gas: u32,
-    pub(crate) gas_price: u32,
+    #[serde(default)]
+    pub(crate) senders: Option<String>,
+    #[serde(default, deserialize_with = "gas_limit")]
+    pub(crate) gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) gas_price: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) max_fee_per_gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) max_priority_fee_per_gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) access_list: Option<AccessList>,
+    #[serde(default)]
+    pub(crate) value: Option<String>,
     pub(crate) args: String,
 }
 
@@ -126,12 +823,43 @@ pub(crate) struct CallJsonObj {
     pub(crate) contract_addr: String,
     pub(crate) abi_path: String,
     pub(crate) sec_key: String,
-    pub(crate) gas: u32,
-    pub(crate) gas_price: u32,
+    #[serde(default)]
+    pub(crate) senders: Option<String>,
+    #[serde(default, deserialize_with = "gas_limit")]
+    pub(crate) gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) gas_price: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) max_fee_per_gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) max_priority_fee_per_gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) access_list: Option<AccessList>,
+    #[serde(default)]
+    pub(crate) value: Option<String>,
     pub(crate) func_name: String,
     pub(crate) args: String,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct TransferJsonObj {
+    pub(crate) sec_key: String,
+    #[serde(default)]
+    pub(crate) senders: Option<String>,
+    pub(crate) to: String,
+    pub(crate) amount: String,
+    #[serde(default, deserialize_with = "gas_limit")]
+    pub(crate) gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) gas_price: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) max_fee_per_gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) max_priority_fee_per_gas: Option<GasValue>,
+    #[serde(default)]
+    pub(crate) access_list: Option<AccessList>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct DeployJson {
     pub(crate) deploy_obj: Vec<DeployJsonObj>,
@@ -142,6 +870,11 @@ pub(crate) struct CallJson {
     pub(crate) call_obj: Vec<CallJsonObj>,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct TransferJson {
+    pub(crate) transfer_obj: Vec<TransferJsonObj>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct QueryJson {
     pub(crate) contract_addr: String,
@@ -150,6 +883,12 @@ pub(crate) struct QueryJson {
     pub(crate) args: String,
 }
 
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct LogsJson {
+    pub(crate) contract_addr: String,
+    pub(crate) abi_path: String,
+}
+
 pub(crate) async fn parse_deploy_json(pat: &PathBuf) -> anyhow::Result<DeployJson> {
     let deploy_json_bytes = fs::read(pat).await?;
     let deply_json_obj: DeployJson = serde_json::from_slice(deploy_json_bytes.as_slice())?;
@@ -171,6 +910,72 @@ pub(crate) async fn parse_query_json(pat: PathBuf) -> anyhow::Result<QueryJson>
     return Ok(query_json_obj);
 }
 
+pub(crate) async fn parse_logs_json(pat: PathBuf) -> anyhow::Result<LogsJson> {
+    let logs_json_bytes = fs::read(pat).await?;
+    let logs_json_obj: LogsJson = serde_json::from_slice(logs_json_bytes.as_slice())?;
+
+    return Ok(logs_json_obj);
+}
+
+pub(crate) async fn parse_transfer_json(pat: PathBuf) -> anyhow::Result<TransferJson> {
+    let transfer_json_bytes = fs::read(pat).await?;
+    let transfer_json_obj: TransferJson = serde_json::from_slice(transfer_json_bytes.as_slice())?;
+
+    return Ok(transfer_json_obj);
+}
+
+// block number as decimal, 0x hex or one of latest, pending and earliest
+pub(crate) fn parse_block_number(s: &str) -> anyhow::Result<BlockNumber> {
+    let s = s.trim();
+    match s {
+        "latest" => Ok(BlockNumber::Latest),
+        "pending" => Ok(BlockNumber::Pending),
+        "earliest" => Ok(BlockNumber::Earliest),
+        _ => match s.strip_prefix("0x") {
+            Some(hex_str) => Ok(BlockNumber::Number(
+                u64::from_str_radix(hex_str, 16)?.into(),
+            )),
+            None => Ok(BlockNumber::Number(s.parse::<u64>()?.into())),
+        },
+    }
+}
+
+// parse amount like "100", "100wei", "20gwei" or "1.5FRA" into wei
+pub(crate) fn parse_amount(amount: &str) -> anyhow::Result<U256> {
+    let amount = amount.trim();
+    let (num, decimals) = if let Some(num) = amount.strip_suffix("gwei") {
+        (num, 9)
+    } else if let Some(num) = amount.strip_suffix("wei") {
+        (num, 0)
+    } else if let Some(num) = amount.strip_suffix("FRA") {
+        (num, 18)
+    } else {
+        (amount, 0)
+    };
+    let num = num.trim();
+
+    let (int_part, frac_part) = match num.split_once('.') {
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (num, ""),
+    };
+    if int_part.is_empty() && frac_part.is_empty() {
+        bail!("amount format error: {}", amount);
+    }
+    if frac_part.len() > decimals {
+        bail!("amount has too many decimal places: {}", amount);
+    }
+
+    let digits = format!("{}{:0<width$}", int_part, frac_part, width = decimals);
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        bail!("amount format error: {}", amount);
+    }
+
+    match U256::from_dec_str(&digits) {
+        Ok(v) => Ok(v),
+        Err(e) => bail!("amount format error: {}: {:?}", amount, e),
+    }
+}
+
 pub(crate) fn parse_args_csv(args: &str) -> anyhow::Result<Vec<Token>> {
     let mut res: Vec<Token> = Vec::new();
 
@@ -206,3 +1011,79 @@ pub(crate) fn parse_args_csv(args: &str) -> anyhow::Result<Vec<Token>> {
 
     return Ok(res);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts() {
+        assert_eq!(parse_amount("100").unwrap(), U256::from(100));
+        assert_eq!(parse_amount("100wei").unwrap(), U256::from(100));
+        assert_eq!(
+            parse_amount("20gwei").unwrap(),
+            U256::from(20_000_000_000u64)
+        );
+        assert_eq!(
+            parse_amount(" 1.5gwei ").unwrap(),
+            U256::from(1_500_000_000u64)
+        );
+    }
+
+    #[test]
+    fn fra_amounts() {
+        let fra = U256::exp10(18);
+        assert_eq!(parse_amount("1FRA").unwrap(), fra);
+        assert_eq!(parse_amount("1.5FRA").unwrap(), fra * 3 / 2);
+        assert_eq!(parse_amount(".5FRA").unwrap(), fra / 2);
+        assert_eq!(
+            parse_amount("0.000000000000000001FRA").unwrap(),
+            U256::one()
+        );
+    }
+
+    #[test]
+    fn bad_amounts() {
+        // more decimals than the unit has
+        assert!(parse_amount("1.5").is_err());
+        assert!(parse_amount("1.0000000001gwei").is_err());
+        assert!(parse_amount("0.0000000000000000001FRA").is_err());
+        assert!(parse_amount("").is_err());
+        assert!(parse_amount("FRA").is_err());
+        assert!(parse_amount("-1").is_err());
+        assert!(parse_amount("1e18").is_err());
+        assert!(parse_amount("1 000").is_err());
+    }
+
+    #[test]
+    fn amount_overflow() {
+        let max = U256::MAX.to_string();
+        assert_eq!(parse_amount(&max).unwrap(), U256::MAX);
+        assert!(parse_amount(&format!("{}0", max)).is_err());
+        assert!(parse_amount(
+            "115792089237316195423570985008687907853269984665640564039457584007913129639936"
+        )
+        .is_err());
+        assert!(
+            parse_amount("115792089237316195423570985008687907853269984665640564039458FRA")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn gas_limits() {
+        assert!(matches!(parse_gas_limit("auto"), Ok(GasValue::Auto)));
+        assert!(
+            matches!(parse_gas_limit("21000"), Ok(GasValue::Fixed(gas)) if gas == 21000.into())
+        );
+        assert!(parse_gas_limit("21000gwei").is_err());
+        assert!(parse_gas_limit("1.5").is_err());
+        assert!(
+            matches!("10gwei".parse::<GasValue>(), Ok(GasValue::Fixed(price)) if price == 10_000_000_000u64.into())
+        );
+
+        assert_eq!(parse_gas_multiplier("1.2").unwrap(), 1.2);
+        assert!(parse_gas_multiplier("0.9").is_err());
+        assert!(parse_gas_multiplier("inf").is_err());
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{
+    rate_limit::{RateLimits, TokenBucket},
+    retry::{is_transient, RpcError},
+};
+use anyhow::bail;
+use std::{
+    future::Future,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+const MAX_UNHEALTHY_SECS: u64 = 60;
+
+// how tasks are spread over the rpc endpoints
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Balance {
+    RoundRobin,
+    LeastInFlight,
+    Weighted,
+}
+
+impl FromStr for Balance {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "round-robin" => Ok(Balance::RoundRobin),
+            "least-in-flight" => Ok(Balance::LeastInFlight),
+            "weighted" => Ok(Balance::Weighted),
+            _ => bail!(
+                "unknown balance {}, expect round-robin, least-in-flight or weighted",
+                s
+            ),
+        }
+    }
+}
+
+pub(crate) struct Endpoint {
+    pub(crate) url: String,
+    weight: u32,
+    in_flight: AtomicU32,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency_ms: AtomicU64,
+    // consecutive failures, the unhealthy time doubles with each
+    failures: AtomicU32,
+    unhealthy_until: Mutex<Option<Instant>>,
+    limit: Option<TokenBucket>,
+}
+
+pub(crate) struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    balance: Balance,
+    next: AtomicUsize,
+    // smooth weighted round-robin state
+    current_weights: Mutex<Vec<i64>>,
+}
+
+impl Endpoints {
+    // endpoints from repeated --rpc-url and a --rpc-list file of "<url> [weight] [rate]" lines
+    pub(crate) async fn load(
+        rpc_urls: Vec<String>,
+        rpc_list: Option<PathBuf>,
+        balance: Balance,
+        limits: &RateLimits,
+    ) -> anyhow::Result<Self> {
+        let mut urls: Vec<(String, u32, Option<f64>)> = rpc_urls
+            .into_iter()
+            .map(|url| (url, 1, limits.endpoint_rate))
+            .collect();
+        if let Some(rpc_list) = rpc_list {
+            let list = tokio::fs::read_to_string(&rpc_list).await?;
+            for line in list.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let mut fields = line.split_whitespace();
+                let url = fields.next().unwrap().to_string();
+                let weight = match fields.next() {
+                    Some(weight) => weight.parse()?,
+                    None => 1,
+                };
+                let rate = match fields.next() {
+                    Some(rate) => Some(rate.parse()?),
+                    None => limits.endpoint_rate,
+                };
+                urls.push((url, weight, rate));
+            }
+        }
+        if urls.is_empty() {
+            bail!("no rpc url given");
+        }
+
+        let mut endpoints = Vec::with_capacity(urls.len());
+        for (url, weight, rate) in urls {
+            let limit = match rate {
+                Some(rate) => Some(TokenBucket::new(rate, limits.burst)?),
+                None => None,
+            };
+            endpoints.push(Endpoint {
+                url,
+                weight,
+                in_flight: AtomicU32::new(0),
+                requests: AtomicU64::new(0),
+                errors: AtomicU64::new(0),
+                latency_ms: AtomicU64::new(0),
+                failures: AtomicU32::new(0),
+                unhealthy_until: Mutex::new(None),
+                limit,
+            });
+        }
+
+        Ok(Endpoints {
+            current_weights: Mutex::new(vec![0; endpoints.len()]),
+            endpoints,
+            balance,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    // the first endpoint, used for the checks before a run
+    pub(crate) fn primary(&self) -> &str {
+        &self.endpoints[0].url
+    }
+
+    pub(crate) fn urls(&self) -> impl Iterator<Item = &str> {
+        self.endpoints.iter().map(|e| e.url.as_str())
+    }
+
+    // pick an endpoint for the next task and take its rate limit token, before the task is timed,
+    // the calls of a warm-up task keep the endpoint health but stay out of its stats
+    pub(crate) async fn lease(self: &Arc<Self>, warming: bool) -> Lease {
+        let mut index = self.pick();
+        while let Some(limit) = &self.endpoints[index].limit {
+            limit.acquire().await;
+            // it may have failed while the task waited for the token, then the token of the next
+            // healthy pick is taken, all of them unhealthy keep this one
+            let now = Instant::now();
+            if self.is_healthy(index, now) {
+                break;
+            }
+            let next = self.pick();
+            if !self.is_healthy(next, now) {
+                break;
+            }
+            index = next;
+        }
+        self.endpoints[index]
+            .in_flight
+            .fetch_add(1, Ordering::AcqRel);
+
+        Lease {
+            endpoints: self.clone(),
+            index,
+            warming,
+        }
+    }
+
+    fn is_healthy(&self, index: usize, now: Instant) -> bool {
+        match *self.endpoints[index].unhealthy_until.lock().unwrap() {
+            Some(until) => until <= now,
+            None => true,
+        }
+    }
+
+    fn pick(&self) -> usize {
+        let now = Instant::now();
+        let healthy: Vec<usize> = (0..self.endpoints.len())
+            .filter(|i| self.is_healthy(*i, now))
+            .collect();
+        // all unhealthy, keep going with the one which recovers first
+        if healthy.is_empty() {
+            return (0..self.endpoints.len())
+                .min_by_key(|i| *self.endpoints[*i].unhealthy_until.lock().unwrap())
+                .unwrap();
+        }
+
+        let next = self.next.fetch_add(1, Ordering::AcqRel);
+        match self.balance {
+            Balance::RoundRobin => healthy[next % healthy.len()],
+            Balance::LeastInFlight => {
+                // ties go round-robin
+                let offset = next % healthy.len();
+                let rotated = healthy[offset..].iter().chain(&healthy[..offset]);
+                *rotated
+                    .min_by_key(|i| self.endpoints[**i].in_flight.load(Ordering::Acquire))
+                    .unwrap()
+            }
+            Balance::Weighted => {
+                let mut current = self.current_weights.lock().unwrap();
+                let mut total = 0;
+                for i in &healthy {
+                    current[*i] += self.endpoints[*i].weight as i64;
+                    total += self.endpoints[*i].weight as i64;
+                }
+                let picked = *healthy.iter().max_by_key(|i| current[**i]).unwrap();
+                current[picked] -= total;
+                picked
+            }
+        }
+    }
+
+    pub(crate) fn print_stats(&self) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+
+        println!(
+            "{:<40} {:>10} {:>10} {:>16}",
+            "endpoint", "requests", "errors", "avg latency ms"
+        );
+        for endpoint in &self.endpoints {
+            let requests = endpoint.requests.load(Ordering::Acquire);
+            let latency_ms = endpoint.latency_ms.load(Ordering::Acquire);
+            println!(
+                "{:<40} {:>10} {:>10} {:>16}",
+                endpoint.url,
+                requests,
+                endpoint.errors.load(Ordering::Acquire),
+                if requests == 0 {
+                    0
+                } else {
+                    latency_ms / requests
+                }
+            );
+        }
+    }
+}
+
+// the endpoint picked for one task, in flight until the task drops it
+pub(crate) struct Lease {
+    endpoints: Arc<Endpoints>,
+    index: usize,
+    warming: bool,
+}
+
+impl Lease {
+    // whether the task runs in the warm-up, its retries stay out of the stats too
+    pub(crate) fn warming(&self) -> bool {
+        self.warming
+    }
+
+    // run `f` with the url of the endpoint and record its latency and result, a task which fails
+    // is not sent again elsewhere, its transaction may have gone through
+    pub(crate) async fn call<F, Fut, R, E>(&self, f: F) -> Result<R, E>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        E: RpcError,
+    {
+        let endpoint = &self.endpoints.endpoints[self.index];
+        let beg = Instant::now();
+
+        let res = f(endpoint.url.clone()).await;
+
+        if !self.warming {
+            endpoint.requests.fetch_add(1, Ordering::AcqRel);
+            endpoint
+                .latency_ms
+                .fetch_add(beg.elapsed().as_millis() as u64, Ordering::AcqRel);
+            if res.is_err() {
+                endpoint.errors.fetch_add(1, Ordering::AcqRel);
+            }
+        }
+        match &res {
+            Ok(_) => {
+                endpoint.failures.store(0, Ordering::Release);
+                *endpoint.unhealthy_until.lock().unwrap() = None;
+            }
+            // a revert or a rejected transaction says nothing about the endpoint
+            Err(e) if !is_transient(e) => {}
+            Err(_) => {
+                let failures = endpoint.failures.fetch_add(1, Ordering::AcqRel);
+                let secs = (1u64 << failures.min(6)).min(MAX_UNHEALTHY_SECS);
+                *endpoint.unhealthy_until.lock().unwrap() =
+                    Some(Instant::now() + Duration::from_secs(secs));
+            }
+        }
+
+        res
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.endpoints.endpoints[self.index]
+            .in_flight
+            .fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load_profile::LoadTarget;
+
+    #[tokio::test]
+    async fn lease_takes_token_of_leased() {
+        let limits = RateLimits {
+            rate: None,
+            endpoint_rate: Some(10.0),
+            sender_rate: None,
+            burst: 1,
+            load_profile: None,
+            load_target: LoadTarget::Rate,
+            max_in_flight: 1000,
+        };
+        let endpoints = Arc::new(
+            Endpoints::load(
+                vec!["http://a".to_string(), "http://b".to_string()],
+                None,
+                Balance::RoundRobin,
+                &limits,
+            )
+            .await
+            .unwrap(),
+        );
+        assert_eq!(endpoints.lease(false).await.index, 0);
+        assert_eq!(endpoints.lease(false).await.index, 1);
+
+        // a fails while the next task waits for its token, the task goes to b with b's token
+        let failing = endpoints.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            *failing.endpoints[0].unhealthy_until.lock().unwrap() =
+                Some(Instant::now() + Duration::from_secs(MAX_UNHEALTHY_SECS));
+        });
+        assert_eq!(endpoints.lease(false).await.index, 1);
+        let limit = endpoints.endpoints[1].limit.as_ref().unwrap();
+        assert!(limit.ready_in() > Duration::from_millis(50));
+    }
+}
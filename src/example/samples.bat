@@ -1,7 +1,27 @@
+@REM state-changing commands only run on allowlisted chain ids, set it to the test network one
+set PLATFORM_TOOL_ALLOWED_CHAIN_IDS=2152
+
+
+@REM config files refer to the secret key as env:TEST_SEC_KEY, file:path or keystore:path
+set TEST_SEC_KEY=hex_secret_key_of_the_test_account
+
+
 @REM test get balance
 cargo run  -- account getbalance -u http://172.25.210.112:9999 0xfBd4505Ab8FA67747E5bfba0F495205c633F18b6 
 
 
+@REM test transfer once
+cargo run -- account transfer -u http://172.25.210.112:9999 --from-key env:TEST_SEC_KEY --to 0xfBd4505Ab8FA67747E5bfba0F495205c633F18b6 --amount 1FRA
+
+
+@REM test transfer workload
+cargo run -- account transfer -u http://172.25.210.112:9999 -g .\src\example\transfer.json
+
+
+@REM test import the key into a keystore, keystore password is asked or read from PLATFORM_TOOL_KEYSTORE_PASSWORD
+cargo run -- account import --key env:TEST_SEC_KEY --keystore .\src\example\test.keystore
+
+
 @REM test deploy once
 cargo run -- contract deploy -u http://172.25.210.112:9999 -g .\src\example\deploy_contract.json
 
@@ -10,6 +30,10 @@ cargo run -- contract deploy -u http://172.25.210.112:9999 -g .\src\example\depl
 cargo run -- contract call -u http://172.25.210.112:9999 -g .\src\example\call_contract.json
 
 
+@REM test call with 1559 transaction
+cargo run -- contract call -u http://172.25.210.112:9999 -g .\src\example\call_contract.json --tx-type 1559
+
+
 @REM test query
 cargo run -- contract query -u http://172.25.210.112:9999 -g .\src\example\query_contract.json
 
@@ -21,3 +45,59 @@ cargo run -- contract deploy -u http://172.25.210.112:9999 -g .\src\example\depl
 @REM test call 1000 times
 cargo run -- contract call -u http://172.25.210.112:9999 -g .\src\example\call_contract.json -c 1000
 
+
+@REM sign a message and recover the signer
+cargo run -- sign message --key env:TEST_SEC_KEY --message hello
+cargo run -- sign recover --message hello --signature 0x_signature_printed_above
+
+@REM transfer signed by a remote signer service (eth_signTransaction)
+cargo run -- account transfer -u http://172.25.210.112:9999 --from-key remote:http://127.0.0.1:8550#0xfBd4505Ab8FA67747E5bfba0F495205c633F18b6 --to 0xfBd4505Ab8FA67747E5bfba0F495205c633F18b6 --amount 0.01FRA
+
+@REM inspect a transaction, its receipt with decoded logs, and the latest block
+cargo run -- tx get -u http://172.25.210.112:9999 0x_transaction_hash
+cargo run -- tx receipt -u http://172.25.210.112:9999 0x_transaction_hash --abi .\src\example\test.abi
+cargo run -- block get -u http://172.25.210.112:9999 latest --full --json
+
+@REM call and print the events each call emitted
+cargo run -- contract call -u http://172.25.210.112:9999 -g .\src\example\call_contract.json --decode-logs
+
+@REM decoded events of the contract in a block range as json lines
+cargo run -- contract logs -u http://172.25.210.112:9999 -g .\src\example\query_contract.json --from-block 0 --to-block latest --out logs.jsonl
+
+@REM follow contract events, resuming from the saved height
+cargo run -- contract watch -u ws://172.25.210.112:9998 -g .\src\example\query_contract.json --event Event_name --state watch_state.json
+
+@REM the same workload over WebSocket, receipts are waited on new heads
+cargo run -- contract call -u ws://172.25.210.112:9998 -g .\src\example\call_contract.json --decode-logs
+
+@REM spread a workload over several rpc endpoints, failed endpoints are skipped for a while
+cargo run -- account transfer -u http://172.25.210.112:9999 -u ws://172.25.210.112:9998 --balance least-in-flight -g .\src\example\transfer.json
+
+@REM retry transport errors, 429/503 and a full txpool up to 5 attempts, starting at 200ms
+cargo run -- contract call -u http://172.25.210.112:9999 -g .\src\example\call_contract.json --max-attempts 5 --retry-backoff 200 --retry-jitter 0.3
+
+@REM at most 20 tasks a second overall, 5 per endpoint and 1 per sender
+cargo run -- account transfer -u http://172.25.210.112:9999 -g .\src\example\transfer.json --rate 20 --endpoint-rate 5 --sender-rate 1 --burst 2
+
+@REM rpc behind an authenticating proxy with an API key header and a private CA
+cargo run -- contract call -u https://staging-rpc.example.com -g .\src\example\call_contract.json --rpc-header "X-Api-Key: %API_KEY%" --rpc-bearer-token %RPC_TOKEN% --rpc-ca-cert .\staging-ca.pem
+
+@REM named networks from networks.toml instead of the rpc url, key:<name> picks a key of the network
+cargo run -- account transfer --network devnet --from-key key:faucet --to 0xfBd4505Ab8FA67747E5bfba0F495205c633F18b6 --amount 1FRA
+cargo run -- contract call --network devnet -g .\src\example\call_contract.json
+
+@REM deploys are recorded in deployments\<network>.json, "@test" in contract_addr picks the address of the one named test
+cargo run -- contract deploy --network devnet -g .\src\example\deploy_contract.json
+cargo run -- contract query --network devnet -g .\src\example\query_contract.json
+
+@REM deploy, call, query and assert steps of one file, later steps use ${steps.<name>.<output>} of earlier ones
+cargo run -- scenario run -u http://172.25.210.112:9999 .\src\example\scenario.yaml
+
+@REM change the start rate over time and report each phase, to find where latency degrades
+cargo run -- account transfer -u http://172.25.210.112:9999 -g .\src\example\transfer.json --load-profile ramp:10:200:120s
+cargo run -- account transfer -u http://172.25.210.112:9999 -g .\src\example\transfer.json --load-profile steps:10,50,100:30s
+cargo run -- account transfer -u http://172.25.210.112:9999 -g .\src\example\transfer.json --load-profile spike:20:200:30s:10s
+
+@REM the first 30 seconds or first 100 tasks run but stay out of the stats
+cargo run -- account transfer -u http://172.25.210.112:9999 -g .\src\example\transfer.json --warmup 30s
+cargo run -- contract call -u http://172.25.210.112:9999 -g .\src\example\call_contract.json --warmup-count 100
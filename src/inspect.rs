@@ -0,0 +1,354 @@
+use crate::{keys::checksum_address, transport::connect};
+use anyhow::bail;
+use serde_json::{json, Value};
+use web3::{
+    ethabi::{
+        self,
+        token::{LenientTokenizer, Tokenizer},
+        ParamType, RawLog, Token,
+    },
+    signing::keccak256,
+    types::{
+        BlockId, BlockNumber, FilterBuilder, Log, Transaction, TransactionId, TransactionReceipt,
+        H160, H256, U256,
+    },
+};
+
+// hex quantities printed as decimal numbers in the pretty output
+const QUANTITY_FIELDS: [&str; 22] = [
+    "baseFeePerGas",
+    "blockNumber",
+    "chainId",
+    "cumulativeGasUsed",
+    "difficulty",
+    "effectiveGasPrice",
+    "gas",
+    "gasLimit",
+    "gasPrice",
+    "gasUsed",
+    "logIndex",
+    "maxFeePerGas",
+    "maxPriorityFeePerGas",
+    "nonce",
+    "number",
+    "size",
+    "status",
+    "timestamp",
+    "totalDifficulty",
+    "transactionIndex",
+    "type",
+    "value",
+];
+
+pub(crate) async fn get_transaction(
+    rpc_url: &str,
+    hash: H256,
+) -> web3::Result<Option<Transaction>> {
+    let web3 = connect(rpc_url).await?;
+
+    web3.eth().transaction(TransactionId::Hash(hash)).await
+}
+
+pub(crate) async fn get_receipt(
+    rpc_url: &str,
+    hash: H256,
+) -> web3::Result<Option<TransactionReceipt>> {
+    let web3 = connect(rpc_url).await?;
+
+    web3.eth().transaction_receipt(hash).await
+}
+
+// block as json, `full` includes the transaction objects instead of their hashes
+pub(crate) async fn get_block(
+    rpc_url: &str,
+    number: BlockNumber,
+    full: bool,
+) -> anyhow::Result<Option<Value>> {
+    let web3 = connect(rpc_url).await?;
+
+    let block = if full {
+        match web3.eth().block_with_txs(BlockId::Number(number)).await? {
+            Some(block) => Some(serde_json::to_value(block)?),
+            None => None,
+        }
+    } else {
+        match web3.eth().block(BlockId::Number(number)).await? {
+            Some(block) => Some(serde_json::to_value(block)?),
+            None => None,
+        }
+    };
+
+    Ok(block)
+}
+
+// receipt as json, logs matching an event of the abi get "event" and "args" fields
+pub(crate) fn receipt_value(
+    receipt: &TransactionReceipt,
+    abi: Option<&ethabi::Contract>,
+) -> anyhow::Result<Value> {
+    let mut value = serde_json::to_value(receipt)?;
+
+    if let Some(abi) = abi {
+        if let Some(logs) = value["logs"].as_array_mut() {
+            for (log, log_value) in receipt.logs.iter().zip(logs) {
+                if let (Some((event, args)), Some(obj)) =
+                    (decode_log(abi, log), log_value.as_object_mut())
+                {
+                    obj.insert("event".into(), Value::String(event));
+                    obj.insert("args".into(), Value::Object(args.into_iter().collect()));
+                }
+            }
+        }
+    }
+
+    Ok(value)
+}
+
+// event name and arguments in abi order of a log, None when no event of the abi matches it
+pub(crate) fn decode_log(
+    abi: &ethabi::Contract,
+    log: &Log,
+) -> Option<(String, Vec<(String, Value)>)> {
+    let topic = log.topics.first()?;
+    let event = abi
+        .events()
+        .find(|event| !event.anonymous && event.signature() == *topic)?;
+    let raw_log = RawLog {
+        topics: log.topics.clone(),
+        data: log.data.0.clone(),
+    };
+    let parsed = event.parse_log(raw_log).ok()?;
+
+    let args = parsed
+        .params
+        .into_iter()
+        .map(|param| (param.name, token_value(param.value)))
+        .collect();
+
+    Some((event.name.clone(), args))
+}
+
+// abi value as json, numbers are decimal strings so they keep their precision
+pub(crate) fn token_value(token: Token) -> Value {
+    match token {
+        Token::Address(address) => Value::String(checksum_address(&address)),
+        Token::Uint(n) => Value::String(n.to_string()),
+        Token::Int(n) => {
+            if n.bit(255) {
+                let abs = (!n).overflowing_add(U256::one()).0;
+                Value::String(format!("-{}", abs))
+            } else {
+                Value::String(n.to_string())
+            }
+        }
+        Token::Bool(b) => Value::Bool(b),
+        Token::String(s) => Value::String(s),
+        Token::Bytes(bytes) | Token::FixedBytes(bytes) => {
+            Value::String(format!("0x{}", hex::encode(bytes)))
+        }
+        Token::Array(tokens) | Token::FixedArray(tokens) | Token::Tuple(tokens) => {
+            Value::Array(tokens.into_iter().map(token_value).collect())
+        }
+    }
+}
+
+// one line per log of the receipt, "event Name(arg: value, ..)" when the abi knows the event
+pub(crate) fn print_events(receipt: &TransactionReceipt, abi: &ethabi::Contract) {
+    for log in &receipt.logs {
+        match decode_log(abi, log) {
+            Some((event, args)) => {
+                let args: Vec<String> = args
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, scalar(value)))
+                    .collect();
+                println!("event {}({})", event, args.join(", "));
+            }
+            None => println!("log {:?} topics {:?}", log.address, log.topics),
+        }
+    }
+}
+
+// logs of a contract in a block range, `event` and `topics` narrow them like `eth_getLogs` does
+pub(crate) async fn get_logs(
+    rpc_url: &str,
+    contract_addr: H160,
+    event: Option<&ethabi::Event>,
+    topics: Vec<Option<Vec<H256>>>,
+    from_block: BlockNumber,
+    to_block: BlockNumber,
+) -> web3::Result<Vec<Log>> {
+    let web3 = connect(rpc_url).await?;
+
+    let filter = FilterBuilder::default()
+        .address(vec![contract_addr])
+        .from_block(from_block)
+        .to_block(to_block)
+        .topics(
+            event.map(|event| vec![event.signature()]),
+            topics[0].clone(),
+            topics[1].clone(),
+            topics[2].clone(),
+        )
+        .build();
+
+    web3.eth().logs(filter).await
+}
+
+// json line of a log, logs no event of the abi matches keep their raw topics and data
+pub(crate) fn log_line(abi: &ethabi::Contract, log: &Log) -> Value {
+    let mut line = json!({
+        "blockNumber": log.block_number.map(|n| n.as_u64()),
+        "transactionHash": log.transaction_hash,
+        "logIndex": log.log_index.map(|n| n.as_u64()),
+        "address": checksum_address(&log.address),
+    });
+
+    match decode_log(abi, log) {
+        Some((event, args)) => {
+            line["event"] = Value::String(event);
+            line["args"] = Value::Object(args.into_iter().collect());
+        }
+        None => {
+            line["topics"] = json!(log.topics);
+            line["data"] = json!(log.data);
+        }
+    }
+    if log.removed == Some(true) {
+        line["removed"] = Value::Bool(true);
+    }
+
+    line
+}
+
+// topics 1 to 3 from `--topic` filters like "1=0x..", "from=0x.." or "to=0x..,0x..",
+// a parameter name picks an indexed parameter of the event and encodes the values by its type
+pub(crate) fn topic_filters(
+    event: Option<&ethabi::Event>,
+    filters: &[String],
+) -> anyhow::Result<Vec<Option<Vec<H256>>>> {
+    let mut topics = vec![None, None, None];
+    for filter in filters {
+        let (key, values) = match filter.split_once('=') {
+            Some((key, values)) => (key.trim(), values),
+            None => bail!(
+                "topic filter {} should be <position|name>=<value>[,value]",
+                filter
+            ),
+        };
+        let (position, kind) = match key.parse::<usize>() {
+            Ok(position) if (1..=3).contains(&position) => (position, None),
+            Ok(position) => bail!("topic position {} should be 1, 2 or 3", position),
+            Err(_) => {
+                let event = match event {
+                    Some(event) => event,
+                    None => bail!("topic filter by name {} needs --event", key),
+                };
+                let indexed: Vec<_> = event.inputs.iter().filter(|p| p.indexed).collect();
+                match indexed.iter().position(|p| p.name == key) {
+                    Some(i) => (i + 1, Some(&indexed[i].kind)),
+                    None => bail!("event {} has no indexed parameter {}", event.name, key),
+                }
+            }
+        };
+
+        let mut hashes = Vec::new();
+        for value in values.split(',') {
+            hashes.push(topic_value(kind, value.trim())?);
+        }
+        topics[position - 1] = Some(hashes);
+    }
+
+    Ok(topics)
+}
+
+fn topic_value(kind: Option<&ParamType>, value: &str) -> anyhow::Result<H256> {
+    match kind {
+        // indexed dynamic values are stored as their hash
+        Some(ParamType::String) => Ok(H256(keccak256(value.as_bytes()))),
+        Some(ParamType::Bytes) => Ok(H256(keccak256(&hex::decode(
+            value.strip_prefix("0x").unwrap_or(value),
+        )?))),
+        Some(kind) => {
+            let encoded = ethabi::encode(&[LenientTokenizer::tokenize(kind, value)?]);
+            if encoded.len() != 32 {
+                bail!("topic filter of type {} is not supported", kind);
+            }
+            Ok(H256::from_slice(&encoded))
+        }
+        None => {
+            let bytes = hex::decode(value.strip_prefix("0x").unwrap_or(value))?;
+            if bytes.len() > 32 {
+                bail!("topic {} is longer than 32 bytes", value);
+            }
+            let mut topic = [0u8; 32];
+            topic[32 - bytes.len()..].copy_from_slice(&bytes);
+            Ok(H256(topic))
+        }
+    }
+}
+
+pub(crate) fn print_value(value: &Value, json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(value)?);
+    } else {
+        print_pretty(value, 0);
+    }
+
+    Ok(())
+}
+
+fn print_pretty(value: &Value, indent: usize) {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Object(obj) => {
+            for (key, v) in obj {
+                match v {
+                    Value::Object(o) if !o.is_empty() => {
+                        println!("{}{}:", pad, key);
+                        print_pretty(v, indent + 2);
+                    }
+                    Value::Array(a) if !a.is_empty() => {
+                        println!("{}{}:", pad, key);
+                        print_pretty(v, indent + 2);
+                    }
+                    _ if QUANTITY_FIELDS.contains(&key.as_str()) => {
+                        println!("{}{}: {}", pad, key, quantity(v))
+                    }
+                    _ => println!("{}{}: {}", pad, key, scalar(v)),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for (i, item) in items.iter().enumerate() {
+                match item {
+                    Value::Object(_) | Value::Array(_) => {
+                        println!("{}[{}]", pad, i);
+                        print_pretty(item, indent + 2);
+                    }
+                    _ => println!("{}- {}", pad, scalar(item)),
+                }
+            }
+        }
+        _ => println!("{}{}", pad, scalar(value)),
+    }
+}
+
+fn quantity(value: &Value) -> String {
+    match value.as_str().and_then(|s| s.strip_prefix("0x")) {
+        Some(hex_str) => match U256::from_str_radix(hex_str, 16) {
+            Ok(n) => n.to_string(),
+            Err(_) => scalar(value),
+        },
+        None => scalar(value),
+    }
+}
+
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        v => v.to_string(),
+    }
+}
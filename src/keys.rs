@@ -0,0 +1,310 @@
+use crate::networks::Network;
+use anyhow::{anyhow, bail};
+use bip39::{Language, Mnemonic, Seed};
+use hmac::{Hmac, Mac};
+use lazy_static::lazy_static;
+use rand::RngCore;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use sha2::Sha512;
+use std::{collections::HashMap, env, fs, path::Path, sync::Mutex};
+use web3::{signing::keccak256, types::H160};
+
+const KEYSTORE_PASSWORD_ENV: &str = "PLATFORM_TOOL_KEYSTORE_PASSWORD";
+const HARDENED: u32 = 0x8000_0000;
+// m/44'/60'/0'/0, the sender index is appended as the last level
+const DERIVATION_PATH: [u32; 4] = [44 | HARDENED, 60 | HARDENED, HARDENED, 0];
+
+lazy_static! {
+    // decrypted keystores, so a keystore used by many config entries asks for its password once
+    static ref KEY_CACHE: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+// resolve a `sec_key` value into a hex secret key, the value is one of
+//   <hex>            plaintext secret key
+//   env:VAR          secret key in environment variable VAR
+//   file:path        secret key in a text file
+//   keystore:path    Web3 Secret Storage v3 file, password from PLATFORM_TOOL_KEYSTORE_PASSWORD or prompt
+//   mnemonic:<phrase>[#index]
+//                    key m/44'/60'/0'/0/index (default 0) of a BIP-39 mnemonic,
+//                    the phrase can also be env:VAR or file:path
+//   key:name         one of the above, named in the keys of the --network
+pub(crate) fn resolve_key(network: &Network, key_ref: &str) -> anyhow::Result<String> {
+    let key_ref = &network.expand_key_ref(key_ref)?;
+    if let Some(key) = KEY_CACHE.lock().unwrap().get(key_ref) {
+        return Ok(key.clone());
+    }
+
+    let key = if let Some(var) = key_ref.strip_prefix("env:") {
+        env::var(var).map_err(|e| anyhow!("read secret key from env {} failed: {}", var, e))?
+    } else if let Some(path) = key_ref.strip_prefix("file:") {
+        fs::read_to_string(path)
+            .map_err(|e| anyhow!("read secret key from file {} failed: {}", path, e))?
+    } else if let Some(path) = key_ref.strip_prefix("keystore:") {
+        let password = keystore_password(&format!("password for keystore {}: ", path))?;
+        let key = eth_keystore::decrypt_key(path, password)
+            .map_err(|e| anyhow!("decrypt keystore {} failed: {}", path, e))?;
+        hex::encode(key)
+    } else if let Some(mnemonic) = key_ref.strip_prefix("mnemonic:") {
+        let (phrase, index) = match mnemonic.rsplit_once('#') {
+            Some((phrase, index)) => (phrase, index.trim().parse()?),
+            None => (mnemonic, 0),
+        };
+        derive_keys(&read_mnemonic(phrase)?, &[index])?.remove(0)
+    } else {
+        key_ref.to_string()
+    };
+    let key = normalize_key(&key)?;
+
+    KEY_CACHE
+        .lock()
+        .unwrap()
+        .insert(key_ref.to_string(), key.clone());
+
+    Ok(key)
+}
+
+// key references of a config entry, `senders` is an index range like "0..100" or "0..=99"
+// which picks one secret key per index from a `mnemonic:` key
+pub(crate) fn resolve_keys(
+    network: &Network,
+    key_ref: &str,
+    senders: Option<&str>,
+) -> anyhow::Result<Vec<String>> {
+    let key_ref = &network.expand_key_ref(key_ref)?;
+    let senders = match senders {
+        Some(senders) => senders,
+        // resolved by the signer, it may be a remote one
+        None => return Ok(vec![key_ref.clone()]),
+    };
+    let phrase = match key_ref.strip_prefix("mnemonic:") {
+        Some(phrase) if !phrase.contains('#') => phrase,
+        _ => bail!("senders range needs a mnemonic: key without #index"),
+    };
+
+    let indexes: Vec<u32> = if let Some((beg, end)) = senders.split_once("..=") {
+        (beg.trim().parse()?..=end.trim().parse()?).collect()
+    } else if let Some((beg, end)) = senders.split_once("..") {
+        (beg.trim().parse()?..end.trim().parse()?).collect()
+    } else {
+        vec![senders.trim().parse()?]
+    };
+    if indexes.is_empty() {
+        bail!("senders range {} is empty", senders);
+    }
+
+    derive_keys(&read_mnemonic(phrase)?, &indexes)
+}
+
+// mnemonic phrase given inline, as env:VAR or as file:path
+pub(crate) fn read_mnemonic(src: &str) -> anyhow::Result<String> {
+    let phrase = if let Some(var) = src.strip_prefix("env:") {
+        env::var(var).map_err(|e| anyhow!("read mnemonic from env {} failed: {}", var, e))?
+    } else if let Some(path) = src.strip_prefix("file:") {
+        fs::read_to_string(path)
+            .map_err(|e| anyhow!("read mnemonic from file {} failed: {}", path, e))?
+    } else {
+        src.to_string()
+    };
+
+    Ok(phrase.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+// hex secret keys m/44'/60'/0'/0/i of a BIP-39 mnemonic without passphrase
+pub(crate) fn derive_keys(phrase: &str, indexes: &[u32]) -> anyhow::Result<Vec<String>> {
+    let mnemonic = Mnemonic::from_phrase(phrase, Language::English)
+        .map_err(|e| anyhow!("mnemonic format error: {}", e))?;
+    let seed = Seed::new(&mnemonic, "");
+
+    let secp = Secp256k1::new();
+    let (mut key, mut chain_code) = hmac_sha512(b"Bitcoin seed", seed.as_bytes())?;
+    for index in DERIVATION_PATH {
+        let (k, c) = derive_child(&secp, &key, &chain_code, index)?;
+        key = k;
+        chain_code = c;
+    }
+
+    let mut keys = Vec::with_capacity(indexes.len());
+    for index in indexes {
+        if index & HARDENED != 0 {
+            bail!("sender index {} is out of range", index);
+        }
+        let (k, _) = derive_child(&secp, &key, &chain_code, *index)?;
+        keys.push(hex::encode(k));
+    }
+
+    Ok(keys)
+}
+
+// BIP-32 private parent key to private child key
+fn derive_child(
+    secp: &Secp256k1<secp256k1::All>,
+    key: &[u8; 32],
+    chain_code: &[u8; 32],
+    index: u32,
+) -> anyhow::Result<([u8; 32], [u8; 32])> {
+    let parent = SecretKey::from_slice(key)?;
+
+    let mut data = Vec::with_capacity(37);
+    if index & HARDENED != 0 {
+        data.push(0);
+        data.extend_from_slice(key);
+    } else {
+        data.extend_from_slice(&PublicKey::from_secret_key(secp, &parent).serialize());
+    }
+    data.extend_from_slice(&index.to_be_bytes());
+
+    let (tweak, child_chain_code) = hmac_sha512(chain_code, &data)?;
+    let mut child = parent;
+    child.add_assign(&tweak)?;
+
+    let mut child_key = [0u8; 32];
+    child_key.copy_from_slice(&child[..]);
+
+    Ok((child_key, child_chain_code))
+}
+
+fn hmac_sha512(key: &[u8], data: &[u8]) -> anyhow::Result<([u8; 32], [u8; 32])> {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key)?;
+    mac.update(data);
+    let res = mac.finalize().into_bytes();
+
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&res[..32]);
+    right.copy_from_slice(&res[32..]);
+
+    Ok((left, right))
+}
+
+// write the secret key into a new keystore file, returns the keystore path
+pub(crate) fn write_keystore(sec_key: &str, path: &Path) -> anyhow::Result<String> {
+    let key = hex::decode(normalize_key(sec_key)?)?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy().to_string(),
+        None => bail!("keystore path {:?} has no file name", path),
+    };
+    if path.exists() {
+        bail!("keystore {:?} already exists", path);
+    }
+
+    let password = keystore_password(&format!("password for new keystore {:?}: ", path))?;
+    if env::var(KEYSTORE_PASSWORD_ENV).is_err() {
+        let confirm = rpassword::read_password_from_tty(Some("repeat password: "))?;
+        if confirm != password {
+            bail!("passwords do not match");
+        }
+    }
+
+    // the keystore writes into the file created here, which keeps its owner only mode
+    create_private(path).map_err(|e| anyhow!("write keystore {:?} failed: {}", path, e))?;
+    if let Err(e) =
+        eth_keystore::encrypt_key(dir, &mut rand::thread_rng(), key, password, Some(&name))
+    {
+        let _ = fs::remove_file(path);
+        bail!("write keystore {:?} failed: {}", path, e);
+    }
+
+    Ok(dir.join(name).to_string_lossy().to_string())
+}
+
+// a new file only its owner can read, an error if it exists
+pub(crate) fn create_private(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)
+}
+
+// random secp256k1 secret key in hex
+pub(crate) fn new_key() -> String {
+    let mut rng = rand::thread_rng();
+    let mut key = [0u8; 32];
+    loop {
+        rng.fill_bytes(&mut key);
+        if SecretKey::from_slice(&key).is_ok() {
+            return hex::encode(key);
+        }
+    }
+}
+
+// EIP-55 mixed-case checksum encoding of an address
+pub(crate) fn checksum_address(address: &H160) -> String {
+    let addr = hex::encode(address.as_bytes());
+    let hash = keccak256(addr.as_bytes());
+
+    let mut res = String::from("0x");
+    for (i, c) in addr.chars().enumerate() {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        if nibble >= 8 {
+            res.push(c.to_ascii_uppercase());
+        } else {
+            res.push(c);
+        }
+    }
+
+    res
+}
+
+fn keystore_password(prompt: &str) -> anyhow::Result<String> {
+    match env::var(KEYSTORE_PASSWORD_ENV) {
+        Ok(password) => Ok(password),
+        Err(_) => Ok(rpassword::read_password_from_tty(Some(prompt))?),
+    }
+}
+
+// 64 hex chars without 0x, the format `SecretKey::from_str` accepts
+fn normalize_key(key: &str) -> anyhow::Result<String> {
+    let key = key.trim();
+    let key = key.strip_prefix("0x").unwrap_or(key);
+    if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+        bail!("secret key format error, expect 32 bytes hex");
+    }
+
+    Ok(key.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MNEMONIC: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn checksum() {
+        // the test vectors of EIP-55
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            assert_eq!(checksum_address(&address.parse().unwrap()), address);
+        }
+    }
+
+    #[test]
+    fn derive() {
+        let keys = derive_keys(MNEMONIC, &[0, 1]).unwrap();
+        assert_eq!(
+            keys,
+            [
+                "1ab42cc412b618bdea3a599e3c9bae199ebf030895b039e9db1e30dafb12b727",
+                "9a983cb3d832fbde5ab49d692b7a8bf5b5d232479c99333d0fc8e1d21f1b55b6"
+            ]
+        );
+        assert!(derive_keys("abandon abandon", &[0]).is_err());
+    }
+
+    #[test]
+    fn mnemonic_whitespace() {
+        let phrase = read_mnemonic(&format!("  {}\n", MNEMONIC.replace(' ', "  "))).unwrap();
+        assert_eq!(phrase, MNEMONIC);
+    }
+}
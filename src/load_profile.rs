@@ -0,0 +1,476 @@
+use anyhow::{anyhow, bail};
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// phases a ramp is split into in the report
+const RAMP_PHASES: u32 = 10;
+
+// "30s", "2m", "500ms" or plain seconds
+pub(crate) fn parse_secs(s: &str) -> anyhow::Result<f64> {
+    let s = s.trim();
+    let (num, scale) = if let Some(num) = s.strip_suffix("ms") {
+        (num, 0.001)
+    } else if let Some(num) = s.strip_suffix('s') {
+        (num, 1.0)
+    } else if let Some(num) = s.strip_suffix('m') {
+        (num, 60.0)
+    } else if let Some(num) = s.strip_suffix('h') {
+        (num, 3600.0)
+    } else {
+        (s, 1.0)
+    };
+    let secs = num
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| anyhow!("bad duration {}, expect like 30s, 2m or 500ms", s))?
+        * scale;
+    if !secs.is_finite() || secs < 0.0 {
+        bail!("bad duration {}, expect like 30s, 2m or 500ms", s);
+    }
+
+    Ok(secs)
+}
+
+// what the values of a load profile set
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LoadTarget {
+    // tasks started per second
+    Rate,
+    // tasks running at once
+    Concurrency,
+}
+
+impl FromStr for LoadTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "rate" => Ok(LoadTarget::Rate),
+            "concurrency" => Ok(LoadTarget::Concurrency),
+            _ => bail!("unknown load target {}, expect rate or concurrency", s),
+        }
+    }
+}
+
+fn parse_rate(s: &str) -> anyhow::Result<f64> {
+    let rate = s
+        .trim()
+        .trim_end_matches("/s")
+        .parse::<f64>()
+        .map_err(|_| anyhow!("bad rate {}, expect tasks per second", s))?;
+    if !rate.is_finite() || rate < 0.0 {
+        bail!("bad rate {}, expect tasks per second", s);
+    }
+
+    Ok(rate)
+}
+
+// 28.999999999999996 as 29
+fn fmt_num(n: f64) -> String {
+    format!("{}", (n * 100.0).round() / 100.0)
+}
+
+// target rate of task starts or tasks running at once over time, linear between (second, rate)
+// points, the last rate holds after the last point
+#[derive(Debug, Clone)]
+pub(crate) struct LoadProfile {
+    points: Vec<(f64, f64)>,
+    // start second and name of each phase of the report
+    phases: Vec<(f64, String)>,
+}
+
+impl FromStr for LoadProfile {
+    type Err = anyhow::Error;
+
+    // ramp:<from>:<to>:<duration>, steps:<rate>,<rate>,..:<duration of each>,
+    // spike:<base>:<peak>:<at>:<duration> or a csv file of "<second>,<rate>" lines
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = s.split(':').collect();
+        let points = match fields[..] {
+            ["ramp", from, to, duration] => {
+                let (from, to, duration) =
+                    (parse_rate(from)?, parse_rate(to)?, parse_secs(duration)?);
+                // the same line, split so every part is a phase of its own
+                (0..=RAMP_PHASES)
+                    .map(|i| {
+                        let part = i as f64 / RAMP_PHASES as f64;
+                        (duration * part, from + (to - from) * part)
+                    })
+                    .collect()
+            }
+            ["steps", rates, duration] => {
+                let duration = parse_secs(duration)?;
+                let mut points = Vec::new();
+                for (i, rate) in rates.split(',').enumerate() {
+                    let rate = parse_rate(rate)?;
+                    points.push((duration * i as f64, rate));
+                    points.push((duration * (i + 1) as f64, rate));
+                }
+                points
+            }
+            ["spike", base, peak, at, duration] => {
+                let (base, peak) = (parse_rate(base)?, parse_rate(peak)?);
+                let (at, duration) = (parse_secs(at)?, parse_secs(duration)?);
+                vec![
+                    (0.0, base),
+                    (at, base),
+                    (at, peak),
+                    (at + duration, peak),
+                    (at + duration, base),
+                ]
+            }
+            ["ramp", ..] | ["steps", ..] | ["spike", ..] => bail!(
+                "bad load profile {}, expect ramp:<from>:<to>:<duration>, \
+                 steps:<rate>,<rate>,..:<duration> or spike:<base>:<peak>:<at>:<duration>",
+                s
+            ),
+            _ => {
+                let content = std::fs::read_to_string(s)
+                    .map_err(|e| anyhow!("read load profile {:?} failed: {}", s, e))?;
+                let mut points = Vec::new();
+                for line in content.lines() {
+                    let line = line.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    match line.split_once(',') {
+                        Some((secs, rate)) => points.push((parse_secs(secs)?, parse_rate(rate)?)),
+                        None => bail!("bad line {:?} in {:?}, expect <second>,<rate>", line, s),
+                    }
+                }
+                points
+            }
+        };
+
+        LoadProfile::new(points)
+    }
+}
+
+impl LoadProfile {
+    fn new(mut points: Vec<(f64, f64)>) -> anyhow::Result<Self> {
+        match points.last() {
+            None => bail!("load profile without points"),
+            Some((_, rate)) if *rate <= 0.0 => {
+                bail!("the last rate of a load profile should be above 0")
+            }
+            Some(_) => {}
+        }
+        if points.windows(2).any(|w| w[1].0 < w[0].0) {
+            bail!("the points of a load profile should go forward in time");
+        }
+        // the profile starts at second 0 with the rate of its first point
+        if points[0].0 > 0.0 {
+            points.insert(0, (0.0, points[0].1));
+        }
+
+        // a phase for each part between two points, rate jumps make empty parts
+        let mut phases: Vec<(f64, String)> = Vec::new();
+        let mut last_rate = None;
+        for w in points.windows(2) {
+            let ((beg, from), (end, to)) = (w[0], w[1]);
+            if end <= beg {
+                continue;
+            }
+            if from == to {
+                // plateaus split by points of the same rate stay one phase
+                if last_rate == Some(from) {
+                    continue;
+                }
+                phases.push((beg, format!("{}/s", fmt_num(from))));
+                last_rate = Some(from);
+            } else {
+                phases.push((beg, format!("{}-{}/s", fmt_num(from), fmt_num(to))));
+                last_rate = None;
+            }
+        }
+        let (end, rate) = *points.last().unwrap();
+        if last_rate != Some(rate) {
+            phases.push((end, format!("{}/s", fmt_num(rate))));
+        }
+        Ok(LoadProfile { points, phases })
+    }
+
+    // second of the last point, the tasks should last until then
+    fn end_secs(&self) -> f64 {
+        self.points.last().unwrap().0
+    }
+
+    // the rate at `secs`, after a jump the new one
+    fn rate_at(&self, secs: f64) -> f64 {
+        for w in self.points.windows(2) {
+            let ((beg, from), (end, to)) = (w[0], w[1]);
+            // the windows before end where this one begins, so end > beg here
+            if secs < end {
+                return from + (to - from) * (secs - beg) / (end - beg);
+            }
+        }
+
+        self.points.last().unwrap().1
+    }
+
+    // second at which the profile has started `n` tasks, the first one starts at 0
+    fn start_secs(&self, n: u64) -> f64 {
+        let mut left = n as f64;
+        let mut secs = self.points[0].0;
+        for w in self.points.windows(2) {
+            let ((beg, from), (end, to)) = (w[0], w[1]);
+            if left <= 0.0 {
+                return beg;
+            }
+            let area = (from + to) / 2.0 * (end - beg);
+            if left > area {
+                left -= area;
+                secs = end;
+                continue;
+            }
+            // where the area under the line from `beg` reaches `left`
+            let slope = (to - from) / (end - beg);
+            if slope.abs() < f64::EPSILON {
+                return beg + left / from;
+            }
+            return beg + ((from * from + 2.0 * slope * left).max(0.0).sqrt() - from) / slope;
+        }
+
+        secs + left / self.points.last().unwrap().1
+    }
+
+    fn phase(&self, secs: f64) -> usize {
+        self.phases
+            .iter()
+            .rposition(|(beg, _)| *beg <= secs)
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Default)]
+struct PhaseStat {
+    tasks: u32,
+    // milliseconds of the tasks which succeeded
+    latencies: Vec<u64>,
+}
+
+// starts tasks on the schedule of a load profile and keeps the stats of each of its phases
+pub(crate) struct Pacer {
+    profile: LoadProfile,
+    target: LoadTarget,
+    // ceiling of the tasks running at once
+    max_in_flight: u32,
+    start: Mutex<Instant>,
+    next: AtomicU64,
+    stats: Mutex<Vec<PhaseStat>>,
+}
+
+impl Pacer {
+    pub(crate) fn new(profile: LoadProfile, target: LoadTarget, max_in_flight: u32) -> Self {
+        Pacer {
+            profile,
+            target,
+            max_in_flight,
+            start: Mutex::new(Instant::now()),
+            next: AtomicU64::new(0),
+            stats: Mutex::new(Vec::new()),
+        }
+    }
+
+    // the profile starts over with each run of tasks
+    pub(crate) fn reset(&self) {
+        *self.start.lock().unwrap() = Instant::now();
+        self.next.store(0, Ordering::Release);
+        let mut stats = self.stats.lock().unwrap();
+        stats.clear();
+        stats.resize_with(self.profile.phases.len(), PhaseStat::default);
+    }
+
+    // sleep until the next task is due, with the concurrency target tasks start as others end
+    pub(crate) async fn wait(&self) {
+        if self.target == LoadTarget::Concurrency {
+            return;
+        }
+        let n = self.next.fetch_add(1, Ordering::AcqRel);
+        let due = *self.start.lock().unwrap() + Duration::from_secs_f64(self.profile.start_secs(n));
+        tokio::time::sleep_until(due.into()).await;
+    }
+
+    fn elapsed_secs(&self) -> f64 {
+        self.start.lock().unwrap().elapsed().as_secs_f64()
+    }
+
+    // tasks which may run at once now, the profile sets it with the concurrency target
+    pub(crate) fn max_in_flight(&self) -> u32 {
+        match self.target {
+            LoadTarget::Rate => self.max_in_flight,
+            LoadTarget::Concurrency => {
+                let rate = self.profile.rate_at(self.elapsed_secs());
+                (rate.round() as u32).min(self.max_in_flight)
+            }
+        }
+    }
+
+    // the phase a task starting now belongs to
+    pub(crate) fn phase(&self) -> usize {
+        self.profile.phase(self.elapsed_secs())
+    }
+
+    // called once all tasks started, the phases after it got no tasks
+    pub(crate) fn check_end(&self) {
+        let (secs, end) = (self.elapsed_secs(), self.profile.end_secs());
+        if secs < end {
+            println!(
+                "load profile: the tasks ran out at {}s of {}s, raise the count to load the rest",
+                fmt_num(secs),
+                fmt_num(end)
+            );
+        }
+    }
+
+    pub(crate) fn record(&self, phase: usize, success: bool, millis: u64) {
+        let mut stats = self.stats.lock().unwrap();
+        if let Some(stat) = stats.get_mut(phase) {
+            stat.tasks += 1;
+            if success {
+                stat.latencies.push(millis);
+            }
+        }
+    }
+
+    pub(crate) fn print_stats(&self) {
+        println!(
+            "{:<8} {:<16} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "at", "phase", "tasks", "success", "avg ms", "p50 ms", "p95 ms", "p99 ms"
+        );
+        let mut stats = self.stats.lock().unwrap();
+        for ((beg, name), stat) in self.profile.phases.iter().zip(stats.iter_mut()) {
+            if stat.tasks == 0 {
+                continue;
+            }
+            let latencies = &mut stat.latencies;
+            latencies.sort_unstable();
+            let percentile = |q: f64| match latencies.len() {
+                0 => 0,
+                n => latencies[((n - 1) as f64 * q).round() as usize],
+            };
+            println!(
+                "{:<8} {:<16} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                format!("{}s", fmt_num(*beg)),
+                // tasks at once with the concurrency target, not a rate
+                match self.target {
+                    LoadTarget::Rate => name.as_str(),
+                    LoadTarget::Concurrency => name.trim_end_matches("/s"),
+                },
+                stat.tasks,
+                latencies.len(),
+                if latencies.is_empty() {
+                    0
+                } else {
+                    latencies.iter().sum::<u64>() / latencies.len() as u64
+                },
+                percentile(0.5),
+                percentile(0.95),
+                percentile(0.99)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_secs(profile: &LoadProfile, n: u64, secs: f64) {
+        let got = profile.start_secs(n);
+        assert!(
+            (got - secs).abs() < 1e-9,
+            "task {} starts at {}, not {}",
+            n,
+            got,
+            secs
+        );
+    }
+
+    fn phase_names(profile: &LoadProfile) -> Vec<&str> {
+        profile
+            .phases
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn ramp() {
+        let profile: LoadProfile = "ramp:0:10:10s".parse().unwrap();
+        // rate t at second t, so n tasks by second sqrt(2n)
+        assert_secs(&profile, 0, 0.0);
+        assert_secs(&profile, 2, 2.0);
+        assert_secs(&profile, 50, 10.0);
+        // the last rate holds after the ramp
+        assert_secs(&profile, 60, 11.0);
+        assert_eq!(profile.phases.len(), RAMP_PHASES as usize + 1);
+        assert_eq!(profile.phases[0], (0.0, "0-1/s".to_string()));
+        assert_eq!(profile.phases[10], (10.0, "10/s".to_string()));
+        assert_eq!(profile.rate_at(2.5), 2.5);
+        assert_eq!(profile.end_secs(), 10.0);
+    }
+
+    #[test]
+    fn steps() {
+        let profile: LoadProfile = "steps:1,2:10s".parse().unwrap();
+        assert_secs(&profile, 10, 10.0);
+        assert_secs(&profile, 12, 11.0);
+        assert_secs(&profile, 32, 21.0);
+        assert_eq!(phase_names(&profile), ["1/s", "2/s"]);
+        assert_eq!(profile.phase(9.9), 0);
+        assert_eq!(profile.phase(10.0), 1);
+        assert_eq!(profile.rate_at(10.0), 2.0);
+    }
+
+    #[test]
+    fn spike() {
+        let profile: LoadProfile = "spike:1:10:5s:2s".parse().unwrap();
+        assert_secs(&profile, 5, 5.0);
+        assert_secs(&profile, 15, 6.0);
+        assert_secs(&profile, 26, 8.0);
+        assert_eq!(phase_names(&profile), ["1/s", "10/s", "1/s"]);
+        assert_eq!(profile.phase(6.0), 1);
+        assert_eq!(profile.phase(7.0), 2);
+    }
+
+    #[test]
+    fn zero_rate() {
+        let profile: LoadProfile = "steps:2,0,2:5s".parse().unwrap();
+        assert_secs(&profile, 10, 5.0);
+        // no task starts while the rate is 0
+        assert_secs(&profile, 11, 10.5);
+        assert_eq!(profile.rate_at(7.0), 0.0);
+        assert_eq!(phase_names(&profile), ["2/s", "0/s", "2/s"]);
+
+        assert!("steps:2,0:5s".parse::<LoadProfile>().is_err());
+        assert!(LoadProfile::new(vec![(0.0, 2.0), (5.0, 0.0)]).is_err());
+    }
+
+    #[test]
+    fn bad_profiles() {
+        assert!("ramp:1:2".parse::<LoadProfile>().is_err());
+        assert!("steps:1,x:5s".parse::<LoadProfile>().is_err());
+        assert!("spike:1:-10:5s:2s".parse::<LoadProfile>().is_err());
+        assert!(LoadProfile::new(vec![(5.0, 1.0), (2.0, 1.0)]).is_err());
+        assert!("burst".parse::<LoadTarget>().is_err());
+        assert_eq!(
+            "concurrency".parse::<LoadTarget>().unwrap(),
+            LoadTarget::Concurrency
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_secs("500ms").unwrap(), 0.5);
+        assert_eq!(parse_secs("2m").unwrap(), 120.0);
+        assert_eq!(parse_secs("3").unwrap(), 3.0);
+        assert!(parse_secs("-1s").is_err());
+    }
+}
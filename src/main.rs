@@ -1,15 +1,56 @@
+use anyhow::{anyhow, bail};
+use std::{collections::HashMap, future::Future, io::Write, path::PathBuf, sync::Arc};
 use structopt::StructOpt;
 
+mod address_book;
 mod commands;
+mod endpoints;
+mod inspect;
+mod keys;
+mod load_profile;
 mod multi_tasks;
+mod networks;
+mod preflight;
+mod rate_limit;
+mod retry;
+mod scenario;
+mod sign;
+mod signer;
 mod task_impl;
+mod transport;
+mod watch;
+mod ws;
 
+use address_book::{address_book_path, file_stem, AddressBook, Deployed, Deployment};
 use commands::{
-    parse_args_csv, parse_call_json, parse_deploy_json, parse_query_json, Account, CallJsonObj,
-    Command, Contr, DeployJsonObj, Opt, QueryJson,
+    parse_amount, parse_args_csv, parse_call_json, parse_deploy_json, parse_logs_json,
+    parse_query_json, parse_transfer_json, Account, Block, CallJsonObj, Command, Contr,
+    DeployJsonObj, LogsJson, Opt, QueryJson, Scenario, Sign, TransferJsonObj, Tx, TxType,
 };
-use multi_tasks::multi_tasks_impl;
-use task_impl::{contract_call, contract_deploy, contract_query, get_balance};
+use endpoints::{Balance, Endpoints, Lease};
+use inspect::{
+    get_block, get_logs, get_receipt, get_transaction, log_line, print_events, print_value,
+    receipt_value, topic_filters,
+};
+use keys::{
+    checksum_address, create_private, derive_keys, new_key, read_mnemonic, resolve_key,
+    resolve_keys, write_keystore,
+};
+use multi_tasks::{multi_tasks_impl, print_confirm_stat, print_warmup_stat, Warmup};
+use networks::Network;
+use preflight::{check_spend, PlannedTx};
+use rate_limit::{Limiter, RateLimits};
+use retry::{print_retry_stat, Retry};
+use scenario::Runner;
+use sign::{message_bytes, personal_hash, recover, sign_hash, typed_data_hash};
+use signer::resolve_signer;
+use task_impl::{
+    contract_call, contract_deploy, contract_query, get_balance, get_chain_id, key_address,
+    transfer, wait_receipt, TxParams, CALL_GAS, DEPLOY_GAS, GAS_MULTIPLIER, TRANSFER_GAS,
+};
+use transport::TransportOptions;
+use watch::{watch, WatchParams};
+use web3::types::{H160, U256};
 
 // const MIN_TASK: u32 = 10;
 
@@ -17,18 +58,266 @@ use task_impl::{contract_call, contract_deploy, contract_query, get_balance};
 async fn main() -> anyhow::Result<()> {
     let opt = Opt::from_args();
     // println!("{:#?}", opt);
+    transport::configure(TransportOptions {
+        headers: opt.rpc_headers,
+        basic_auth: opt.rpc_basic_auth,
+        bearer_token: opt.rpc_bearer_token,
+        ca_cert: opt.rpc_ca_cert,
+        insecure: opt.rpc_insecure,
+    })?;
+    let network = networks::select(opt.network.as_deref())?;
 
     match opt.cmd {
         Some(cmd) => match cmd {
             Command::Account(account) => match account {
                 Account::Getbalance(getbalance) => {
-                    let balance = get_balance(&getbalance.rpc_url, &getbalance.account).await?;
+                    let balance =
+                        get_balance(&network.rpc_url(getbalance.rpc_url)?, &getbalance.account)
+                            .await?;
                     println!("account balance {:?}: {}", getbalance.account, balance);
                 }
+                Account::Import(import) => {
+                    let sec_key = resolve_key(&network, &import.key)?;
+                    let path = write_keystore(&sec_key, &import.keystore)?;
+                    println!("address: {}", checksum_address(&key_address(&sec_key)?));
+                    println!("keystore: {}", path);
+                }
+                Account::Export(export) => {
+                    let sec_key = resolve_key(&network, &export.key)?;
+                    match export.out {
+                        Some(out) => {
+                            create_private(&out)
+                                .and_then(|mut file| file.write_all(sec_key.as_bytes()))
+                                .map_err(|e| anyhow!("write {:?} failed: {}", out, e))?;
+                            println!("secret key written: {:?}", out);
+                        }
+                        None => println!("{}", sec_key),
+                    }
+                }
+                Account::New(new) => {
+                    let sec_key = new_key();
+                    println!("address: {}", checksum_address(&key_address(&sec_key)?));
+                    match new.keystore {
+                        Some(keystore) => {
+                            let path = write_keystore(&sec_key, &keystore)?;
+                            println!("keystore: {}", path);
+                        }
+                        None => println!("secret key: {}", sec_key),
+                    }
+                }
+                Account::Address(address) => {
+                    let sec_key = resolve_key(&network, &address.key)?;
+                    println!("{}", checksum_address(&key_address(&sec_key)?));
+                }
+                Account::Derive(derive) => {
+                    let end = match derive.start.checked_add(derive.count) {
+                        Some(end) => end,
+                        None => bail!(
+                            "--start {} plus --count {} is past the last index",
+                            derive.start,
+                            derive.count
+                        ),
+                    };
+                    let indexes: Vec<u32> = (derive.start..end).collect();
+                    let keys = derive_keys(&read_mnemonic(&derive.mnemonic)?, &indexes)?;
+                    for (index, sec_key) in indexes.iter().zip(keys) {
+                        let address = checksum_address(&key_address(&sec_key)?);
+                        if derive.show_keys {
+                            println!("m/44'/60'/0'/0/{} {} {}", index, address, sec_key);
+                        } else {
+                            println!("m/44'/60'/0'/0/{} {}", index, address);
+                        }
+                    }
+                }
+                Account::Transfer(trans) => {
+                    let RunSetup {
+                        endpoints,
+                        limiter,
+                        tx_type,
+                        gas_multiplier,
+                        chain_id,
+                    } = setup_run(
+                        &network,
+                        RunArgs {
+                            rpc_url: trans.rpc_url,
+                            rpc_list: trans.rpc_list,
+                            balance: trans.balance,
+                            limits: &trans.limits,
+                            tx_type: trans.tx_type,
+                            gas_multiplier: trans.gas_multiplier,
+                        },
+                        &opt.allowed_chain_ids,
+                        opt.i_know_this_is_mainnet,
+                    )
+                    .await?;
+                    match trans.config {
+                        None => {
+                            let tx_params = network.gas_defaults(TxParams {
+                                gas: trans.gas,
+                                gas_price: trans.gas_price,
+                                gas_multiplier,
+                                value: trans.amount,
+                                tx_type,
+                                max_fee_per_gas: trans.max_fee_per_gas,
+                                max_priority_fee_per_gas: trans.max_priority_fee_per_gas,
+                                access_list: None,
+                                chain_id: Some(chain_id),
+                            });
+                            let signer = resolve_signer(&network, &trans.from_key.unwrap())?;
+                            let planned = PlannedTx {
+                                from: signer.address(),
+                                default_gas: TRANSFER_GAS,
+                                tx_params: tx_params.clone(),
+                            };
+                            check_spend(
+                                endpoints.primary(),
+                                &[planned],
+                                trans.max_spend,
+                                U256::zero(),
+                            )
+                            .await?;
+
+                            let retry = Retry::new(trans.retry, false);
+                            let transaction_hash = transfer(
+                                endpoints.primary(),
+                                signer.as_ref(),
+                                &trans.to.unwrap(),
+                                tx_params,
+                                &retry,
+                            )
+                            .await?;
+                            println!("transaction hash: {:?}{}", transaction_hash, retry.note());
+                            if let Some(link) =
+                                network.explorer_link("tx", &format!("{:?}", transaction_hash))
+                            {
+                                println!("explorer: {}", link);
+                            }
+                        }
+                        Some(config) => {
+                            let transfer_json = parse_transfer_json(config).await?;
+
+                            let mut vf = Vec::new();
+                            let mut planned = Vec::new();
+                            // one entry per sender when the entry picks a range of mnemonic keys
+                            let mut transfer_objs = Vec::new();
+                            for transfer_obj in transfer_json.transfer_obj {
+                                for sec_key in resolve_keys(
+                                    &network,
+                                    &transfer_obj.sec_key,
+                                    transfer_obj.senders.as_deref(),
+                                )? {
+                                    transfer_objs.push(TransferJsonObj {
+                                        sec_key,
+                                        senders: None,
+                                        ..transfer_obj.clone()
+                                    });
+                                }
+                            }
+                            for transfer_obj in transfer_objs {
+                                let TransferJsonObj {
+                                    sec_key,
+                                    senders: _,
+                                    to,
+                                    amount,
+                                    gas,
+                                    gas_price,
+                                    max_fee_per_gas,
+                                    max_priority_fee_per_gas,
+                                    access_list,
+                                } = transfer_obj;
+                                let signer = resolve_signer(&network, &sec_key)?;
+                                let tx_params = network.gas_defaults(TxParams {
+                                    gas,
+                                    gas_price,
+                                    gas_multiplier,
+                                    value: Some(parse_amount(&amount)?),
+                                    tx_type,
+                                    max_fee_per_gas,
+                                    max_priority_fee_per_gas,
+                                    access_list,
+                                    chain_id: Some(chain_id),
+                                });
+
+                                planned.push(PlannedTx {
+                                    from: signer.address(),
+                                    default_gas: TRANSFER_GAS,
+                                    tx_params: tx_params.clone(),
+                                });
+
+                                let retry_policy = trans.retry;
+                                let sender = signer.address();
+                                let f = move |lease: Lease| async move {
+                                    let retry = &Retry::new(retry_policy, lease.warming());
+                                    let res = lease
+                                        .call(|rpc_url| async move {
+                                            transfer(
+                                                &rpc_url,
+                                                signer.as_ref(),
+                                                &to,
+                                                tx_params,
+                                                retry,
+                                            )
+                                            .await
+                                        })
+                                        .await;
+                                    match res {
+                                        Ok(v) => {
+                                            println!("transaction hash: {:?}{}", v, retry.note());
+                                            return Ok(());
+                                        }
+                                        Err(e) => {
+                                            println!("transfer failed{}: {:?}", retry.note(), e);
+                                            anyhow::bail!("transfer failed");
+                                        }
+                                    };
+                                };
+
+                                vf.push((Some(sender), f));
+                            }
+
+                            check_spend(
+                                endpoints.primary(),
+                                &planned,
+                                trans.max_spend,
+                                U256::zero(),
+                            )
+                            .await?;
+
+                            run_tasks(vf, &endpoints, &limiter, trans.warmup).await?;
+                        }
+                    }
+                }
             },
             Command::Contract(contract) => match contract {
                 Contr::Deploy(deploy) => {
+                    let RunSetup {
+                        endpoints,
+                        limiter,
+                        tx_type,
+                        gas_multiplier,
+                        chain_id,
+                    } = setup_run(
+                        &network,
+                        RunArgs {
+                            rpc_url: deploy.rpc_url,
+                            rpc_list: deploy.rpc_list,
+                            balance: deploy.balance,
+                            limits: &deploy.limits,
+                            tx_type: deploy.tx_type,
+                            gas_multiplier: deploy.gas_multiplier,
+                        },
+                        &opt.allowed_chain_ids,
+                        opt.i_know_this_is_mainnet,
+                    )
+                    .await?;
                     let deploy_json = parse_deploy_json(&deploy.config).await?;
+                    let mut address_book = AddressBook::open(address_book_path(
+                        opt.address_book.clone(),
+                        opt.network.as_deref(),
+                        chain_id,
+                    ))
+                    .await?;
+                    let deployed = Deployed::default();
 
                     // let count = deploy.count;
                     // let total_task;
@@ -42,117 +331,295 @@ async fn main() -> anyhow::Result<()> {
                     // }
 
                     let mut vf = Vec::new();
+                    let mut planned = Vec::new();
+                    // one entry per sender when the entry picks a range of mnemonic keys
+                    let mut deploy_objs = Vec::new();
                     for deploy_obj in deploy_json.deploy_obj {
-                        let rpc_url = deploy.rpc_url.clone();
+                        for sec_key in resolve_keys(
+                            &network,
+                            &deploy_obj.sec_key,
+                            deploy_obj.senders.as_deref(),
+                        )? {
+                            deploy_objs.push(DeployJsonObj {
+                                sec_key,
+                                senders: None,
+                                ..deploy_obj.clone()
+                            });
+                        }
+                    }
+                    for deploy_obj in deploy_objs {
+                        let deployed = deployed.clone();
 
                         let DeployJsonObj {
+                            name,
                             code_path,
                             abi_path,
                             sec_key,
+                            senders: _,
                             gas,
                             gas_price,
+                            max_fee_per_gas,
+                            max_priority_fee_per_gas,
+                            access_list,
+                            value,
                             args,
                         } = deploy_obj;
+                        let signer = resolve_signer(&network, &sec_key)?;
                         let args = parse_args_csv(&args)?;
+                        let tx_params = network.gas_defaults(TxParams {
+                            gas,
+                            gas_price,
+                            gas_multiplier,
+                            value: value.as_deref().map(parse_amount).transpose()?,
+                            tx_type,
+                            max_fee_per_gas,
+                            max_priority_fee_per_gas,
+                            access_list,
+                            chain_id: Some(chain_id),
+                        });
 
-                        let f = move || async move {
-                            match contract_deploy(
-                                &rpc_url, &sec_key, &code_path, &abi_path, gas, gas_price, args,
-                            )
-                            .await
-                            {
-                                Ok(v) => {
-                                    println!("contract address: {:?}", v);
+                        planned.push(PlannedTx {
+                            from: signer.address(),
+                            default_gas: DEPLOY_GAS,
+                            tx_params: tx_params.clone(),
+                        });
+
+                        let retry_policy = deploy.retry;
+                        let sender = signer.address();
+                        let name = match name {
+                            Some(name) => name,
+                            None => file_stem(&abi_path),
+                        };
+                        let book_abi_path = abi_path.clone();
+                        let f = move |lease: Lease| async move {
+                            let retry = &Retry::new(retry_policy, lease.warming());
+                            let res = lease
+                                .call(|rpc_url| async move {
+                                    contract_deploy(
+                                        &rpc_url,
+                                        signer.as_ref(),
+                                        &code_path,
+                                        &abi_path,
+                                        tx_params,
+                                        args,
+                                        retry,
+                                    )
+                                    .await
+                                })
+                                .await;
+                            match res {
+                                Ok((address, receipt)) => {
+                                    println!("contract address: {:?}{}", address, retry.note());
+                                    deployed.lock().unwrap().push((
+                                        name,
+                                        Deployment {
+                                            address,
+                                            abi_path: book_abi_path,
+                                            transaction_hash: receipt.transaction_hash,
+                                            block_number: receipt.block_number.map(|n| n.as_u64()),
+                                            deployer: sender,
+                                        },
+                                    ));
                                     return Ok(());
                                 }
                                 Err(e) => {
-                                    println!("deploy contract failed: {:?}", e);
+                                    println!("deploy contract failed{}: {:?}", retry.note(), e);
                                     anyhow::bail!("deploy failed");
                                 }
                             };
                         };
 
-                        vf.push(f);
+                        vf.push((Some(sender), f));
                     }
 
-                    let (success_task, total_times) = multi_tasks_impl(vf).await?;
-                    println!(
-                        "success task: {} total times: {} average time: {}",
-                        success_task,
-                        total_times,
-                        if success_task == 0 {
-                            0
-                        } else {
-                            total_times / success_task as u128
-                        }
-                    );
+                    check_spend(
+                        endpoints.primary(),
+                        &planned,
+                        deploy.max_spend,
+                        U256::zero(),
+                    )
+                    .await?;
+
+                    run_tasks(vf, &endpoints, &limiter, deploy.warmup).await?;
+                    if address_book.record(&deployed).await {
+                        println!("address book: {:?}", address_book.path());
+                    }
                 }
                 Contr::Call(call) => {
+                    let RunSetup {
+                        endpoints,
+                        limiter,
+                        tx_type,
+                        gas_multiplier,
+                        chain_id,
+                    } = setup_run(
+                        &network,
+                        RunArgs {
+                            rpc_url: call.rpc_url,
+                            rpc_list: call.rpc_list,
+                            balance: call.balance,
+                            limits: &call.limits,
+                            tx_type: call.tx_type,
+                            gas_multiplier: call.gas_multiplier,
+                        },
+                        &opt.allowed_chain_ids,
+                        opt.i_know_this_is_mainnet,
+                    )
+                    .await?;
                     let call_json = parse_call_json(call.config).await?;
+                    let address_book = AddressBook::open(address_book_path(
+                        opt.address_book.clone(),
+                        opt.network.as_deref(),
+                        chain_id,
+                    ))
+                    .await?;
 
                     let mut vf = Vec::new();
+                    let mut planned = Vec::new();
+                    // abis of the decoded logs by path, loaded once for all the tasks
+                    let mut event_abis: HashMap<String, Arc<web3::ethabi::Contract>> =
+                        HashMap::new();
+                    // one entry per sender when the entry picks a range of mnemonic keys
+                    let mut call_objs = Vec::new();
                     for call_obj in call_json.call_obj {
-                        let rpc_url = call.rpc_url.clone();
+                        for sec_key in
+                            resolve_keys(&network, &call_obj.sec_key, call_obj.senders.as_deref())?
+                        {
+                            call_objs.push(CallJsonObj {
+                                sec_key,
+                                senders: None,
+                                ..call_obj.clone()
+                            });
+                        }
+                    }
+                    for call_obj in call_objs {
+                        let retry_policy = call.retry;
 
                         let CallJsonObj {
                             contract_addr,
                             abi_path,
                             sec_key,
+                            senders: _,
                             gas,
                             gas_price,
+                            max_fee_per_gas,
+                            max_priority_fee_per_gas,
+                            access_list,
+                            value,
                             func_name,
                             args,
                         } = call_obj;
+                        let contract_addr = address_book.resolve(&contract_addr)?;
+                        let signer = resolve_signer(&network, &sec_key)?;
                         let args = parse_args_csv(&args)?;
+                        let mut event_abi = None;
+                        if call.decode_logs {
+                            if !event_abis.contains_key(&abi_path) {
+                                let abi = tokio::fs::read(&abi_path).await?;
+                                let abi = web3::ethabi::Contract::load(&abi[..])?;
+                                event_abis.insert(abi_path.clone(), Arc::new(abi));
+                            }
+                            event_abi = event_abis.get(&abi_path).cloned();
+                        }
+                        let tx_params = network.gas_defaults(TxParams {
+                            gas,
+                            gas_price,
+                            gas_multiplier,
+                            value: value.as_deref().map(parse_amount).transpose()?,
+                            tx_type,
+                            max_fee_per_gas,
+                            max_priority_fee_per_gas,
+                            access_list,
+                            chain_id: Some(chain_id),
+                        });
 
-                        let f = move || async move {
-                            match contract_call(
-                                &rpc_url,
-                                &sec_key,
-                                &contract_addr,
-                                &abi_path,
-                                gas,
-                                gas_price,
-                                &func_name,
-                                args,
-                            )
-                            .await
-                            {
-                                Ok(v) => {
-                                    println!("transaction hash: {:?}", v);
+                        planned.push(PlannedTx {
+                            from: signer.address(),
+                            default_gas: CALL_GAS,
+                            tx_params: tx_params.clone(),
+                        });
+
+                        let sender = signer.address();
+                        let f = move |lease: Lease| async move {
+                            let retry = &Retry::new(retry_policy, lease.warming());
+                            let decode_logs = event_abi.is_some();
+                            // the receipt is waited on the endpoint the call was sent to
+                            let res = lease
+                                .call(|rpc_url| async move {
+                                    let v = contract_call(
+                                        &rpc_url,
+                                        signer.as_ref(),
+                                        &contract_addr,
+                                        &abi_path,
+                                        tx_params,
+                                        &func_name,
+                                        args,
+                                        retry,
+                                    )
+                                    .await?;
+                                    println!("transaction hash: {:?}{}", v, retry.note());
+                                    if decode_logs {
+                                        Ok(Some(
+                                            retry
+                                                .run(|| {
+                                                    wait_receipt(
+                                                        &rpc_url,
+                                                        v,
+                                                        retry.receipt_timeout(),
+                                                    )
+                                                })
+                                                .await?,
+                                        ))
+                                    } else {
+                                        Ok::<_, anyhow::Error>(None)
+                                    }
+                                })
+                                .await;
+                            match res {
+                                Ok(None) => return Ok(()),
+                                Ok(Some(receipt)) => {
+                                    if let Some(abi) = &event_abi {
+                                        print_events(&receipt, abi);
+                                    }
+                                    if receipt.status == Some(0.into()) {
+                                        println!(
+                                            "transaction reverted: {:?}",
+                                            receipt.transaction_hash
+                                        );
+                                        anyhow::bail!("call reverted");
+                                    }
                                     return Ok(());
                                 }
                                 Err(e) => {
-                                    println!("call contract failed: {:?}", e);
+                                    println!("call contract failed{}: {:?}", retry.note(), e);
                                     anyhow::bail!("call failed");
                                 }
                             };
                         };
 
-                        vf.push(f);
+                        vf.push((Some(sender), f));
                     }
 
-                    let (success_task, total_times) = multi_tasks_impl(vf).await?;
+                    check_spend(endpoints.primary(), &planned, call.max_spend, U256::zero())
+                        .await?;
 
-                    println!(
-                        "success task: {} total times: {} average time: {}",
-                        success_task,
-                        total_times,
-                        if success_task == 0 {
-                            0
-                        } else {
-                            total_times / success_task as u128
-                        }
-                    );
+                    run_tasks(vf, &endpoints, &limiter, call.warmup).await?;
                 }
                 Contr::Query(query) => {
-                    let rpc_url = query.rpc_url;
+                    let rpc_url = network.rpc_url(query.rpc_url)?;
                     let QueryJson {
                         contract_addr,
                         abi_path,
                         func_name,
                         args,
                     } = parse_query_json(query.config).await?;
+                    let contract_addr = resolve_contract_addr(
+                        &contract_addr,
+                        &rpc_url,
+                        opt.address_book.clone(),
+                        opt.network.as_deref(),
+                    )
+                    .await?;
                     let args = parse_args_csv(&args)?;
 
                     let result =
@@ -160,6 +627,184 @@ async fn main() -> anyhow::Result<()> {
                             .await?;
                     println!("query result: {:?}", result);
                 }
+                Contr::Logs(logs) => {
+                    let LogsJson {
+                        contract_addr,
+                        abi_path,
+                    } = parse_logs_json(logs.config).await?;
+                    let rpc_url = network.rpc_url(logs.rpc_url)?;
+                    let contract_addr = resolve_contract_addr(
+                        &contract_addr,
+                        &rpc_url,
+                        opt.address_book.clone(),
+                        opt.network.as_deref(),
+                    )
+                    .await?;
+                    let abi = web3::ethabi::Contract::load(&tokio::fs::read(&abi_path).await?[..])?;
+                    let event = match &logs.event {
+                        Some(name) => Some(abi.event(name)?),
+                        None => None,
+                    };
+                    let topics = topic_filters(event, &logs.topics)?;
+
+                    let found = get_logs(
+                        &rpc_url,
+                        contract_addr.parse()?,
+                        event,
+                        topics,
+                        logs.from_block,
+                        logs.to_block,
+                    )
+                    .await?;
+                    let mut lines = String::new();
+                    for log in &found {
+                        lines.push_str(&log_line(&abi, log).to_string());
+                        lines.push('\n');
+                    }
+                    match logs.out {
+                        Some(out) => {
+                            tokio::fs::write(&out, lines).await?;
+                            println!("{} logs written: {:?}", found.len(), out);
+                        }
+                        None => print!("{}", lines),
+                    }
+                }
+                Contr::Watch(w) => {
+                    let LogsJson {
+                        contract_addr,
+                        abi_path,
+                    } = parse_logs_json(w.config).await?;
+                    let rpc_url = network.rpc_url(w.rpc_url)?;
+                    let contract_addr = resolve_contract_addr(
+                        &contract_addr,
+                        &rpc_url,
+                        opt.address_book.clone(),
+                        opt.network.as_deref(),
+                    )
+                    .await?;
+                    let abi = web3::ethabi::Contract::load(&tokio::fs::read(&abi_path).await?[..])?;
+                    let event = abi.event(&w.event)?.clone();
+                    let topics = topic_filters(Some(&event), &w.topics)?;
+
+                    let params = WatchParams {
+                        contract_addr: contract_addr.parse()?,
+                        abi,
+                        event,
+                        topics,
+                        from_block: w.from_block,
+                        state: w.state,
+                        poll_interval: w.poll_interval,
+                    };
+                    watch(&rpc_url, params).await?;
+                }
+            },
+            Command::Sign(sign) => match sign {
+                Sign::Message(message) => {
+                    let sec_key = resolve_key(&network, &message.key)?;
+                    let hash = personal_hash(&message_bytes(&message.message));
+                    let signature = sign_hash(&sec_key, &hash)?;
+                    println!("address: {}", checksum_address(&key_address(&sec_key)?));
+                    println!("signature: 0x{}", hex::encode(signature));
+                }
+                Sign::TypedData(typed_data) => {
+                    let sec_key = resolve_key(&network, &typed_data.key)?;
+                    let typed_data_bytes = tokio::fs::read(&typed_data.typed_data).await?;
+                    let hash = typed_data_hash(&serde_json::from_slice(&typed_data_bytes)?)?;
+                    let signature = sign_hash(&sec_key, &hash)?;
+                    println!("address: {}", checksum_address(&key_address(&sec_key)?));
+                    println!("hash: 0x{}", hex::encode(hash));
+                    println!("signature: 0x{}", hex::encode(signature));
+                }
+                Sign::Recover(rec) => {
+                    let hash = match (rec.message, rec.typed_data) {
+                        (Some(message), _) => personal_hash(&message_bytes(&message)),
+                        (None, Some(typed_data)) => {
+                            let typed_data_bytes = tokio::fs::read(typed_data).await?;
+                            typed_data_hash(&serde_json::from_slice(&typed_data_bytes)?)?
+                        }
+                        (None, None) => bail!("message or typed data is needed"),
+                    };
+                    let signature = rec.signature.trim_start_matches("0x");
+                    let address = recover(&hash, &hex::decode(signature)?)?;
+                    println!("{}", checksum_address(&address));
+                }
+            },
+            Command::Tx(tx) => match tx {
+                Tx::Get(get) => {
+                    match get_transaction(&network.rpc_url(get.rpc_url)?, get.hash).await? {
+                        Some(transaction) => {
+                            print_value(&serde_json::to_value(transaction)?, get.json)?
+                        }
+                        None => bail!("transaction {:?} not found", get.hash),
+                    }
+                }
+                Tx::Receipt(receipt) => {
+                    let abi = match receipt.abi {
+                        Some(abi_path) => Some(web3::ethabi::Contract::load(
+                            &tokio::fs::read(abi_path).await?[..],
+                        )?),
+                        None => None,
+                    };
+                    match get_receipt(&network.rpc_url(receipt.rpc_url)?, receipt.hash).await? {
+                        Some(r) => print_value(&receipt_value(&r, abi.as_ref())?, receipt.json)?,
+                        None => bail!("receipt of {:?} not found", receipt.hash),
+                    }
+                }
+            },
+            Command::Block(block) => match block {
+                Block::Get(get) => {
+                    match get_block(&network.rpc_url(get.rpc_url)?, get.number, get.full).await? {
+                        Some(block) => print_value(&block, get.json)?,
+                        None => bail!("block {:?} not found", get.number),
+                    }
+                }
+            },
+            Command::Scenario(scenario) => match scenario {
+                Scenario::Run(run) => {
+                    let RunSetup {
+                        endpoints,
+                        limiter,
+                        tx_type,
+                        gas_multiplier,
+                        chain_id,
+                    } = setup_run(
+                        &network,
+                        RunArgs {
+                            rpc_url: run.rpc_url,
+                            rpc_list: run.rpc_list,
+                            balance: run.balance,
+                            limits: &run.limits,
+                            tx_type: run.tx_type,
+                            gas_multiplier: run.gas_multiplier,
+                        },
+                        &opt.allowed_chain_ids,
+                        opt.i_know_this_is_mainnet,
+                    )
+                    .await?;
+                    let address_book = AddressBook::open(address_book_path(
+                        opt.address_book.clone(),
+                        opt.network.as_deref(),
+                        chain_id,
+                    ))
+                    .await?;
+
+                    let runner = Runner {
+                        endpoints: endpoints.clone(),
+                        limiter,
+                        tx_type,
+                        gas_multiplier,
+                        network,
+                        chain_id,
+                        retry: run.retry,
+                        max_spend: run.max_spend,
+                        planned_spend: tokio::sync::Mutex::new(U256::zero()),
+                        address_book: tokio::sync::Mutex::new(address_book),
+                        deployed: Deployed::default(),
+                    };
+                    runner.run(&run.scenario).await?;
+                    print_retry_stat();
+                    endpoints.print_stats();
+                }
             },
         },
         None => {
@@ -173,3 +818,162 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+// what the commands which run tasks take from the command line for their endpoints, rate limits
+// and transactions
+struct RunArgs<'a> {
+    rpc_url: Vec<String>,
+    rpc_list: Option<PathBuf>,
+    balance: Balance,
+    limits: &'a RateLimits,
+    tx_type: Option<TxType>,
+    gas_multiplier: Option<f64>,
+}
+
+struct RunSetup {
+    endpoints: Arc<Endpoints>,
+    limiter: Arc<Limiter>,
+    tx_type: TxType,
+    gas_multiplier: f64,
+    chain_id: u64,
+}
+
+// endpoints and limits of a run, with the defaults of the network and its chain id checked
+async fn setup_run(
+    network: &Network,
+    args: RunArgs<'_>,
+    allowed_chain_ids: &[u64],
+    i_know_this_is_mainnet: bool,
+) -> anyhow::Result<RunSetup> {
+    let endpoints = Arc::new(
+        Endpoints::load(
+            network.rpc_urls(args.rpc_url, &args.rpc_list),
+            args.rpc_list,
+            args.balance,
+            args.limits,
+        )
+        .await?,
+    );
+    let chain_id = check_chain_id(
+        &endpoints,
+        network.chain_id,
+        allowed_chain_ids,
+        i_know_this_is_mainnet,
+    )
+    .await?;
+
+    Ok(RunSetup {
+        limiter: Arc::new(Limiter::new(args.limits)?),
+        tx_type: args.tx_type.or(network.tx_type).unwrap_or_default(),
+        gas_multiplier: args
+            .gas_multiplier
+            .or(network.gas_multiplier)
+            .unwrap_or(GAS_MULTIPLIER),
+        endpoints,
+        chain_id,
+    })
+}
+
+// run the tasks of a command and print the stats of the run
+async fn run_tasks<F, T>(
+    vf: Vec<(Option<H160>, F)>,
+    endpoints: &Arc<Endpoints>,
+    limiter: &Arc<Limiter>,
+    warmup: Warmup,
+) -> anyhow::Result<()>
+where
+    F: FnOnce(Lease) -> T,
+    T: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let (success_task, total_times) =
+        multi_tasks_impl(vf, endpoints.clone(), limiter.clone(), warmup).await?;
+    print_task_stat(success_task, total_times);
+    print_confirm_stat();
+    print_warmup_stat();
+    limiter.print_phase_stats();
+    print_retry_stat();
+    endpoints.print_stats();
+
+    Ok(())
+}
+
+// refuse to send state-changing transactions to a chain outside the allowlist,
+// all endpoints of a run have to be on the same chain, the one of the --network if it has one
+async fn check_chain_id(
+    endpoints: &Endpoints,
+    network_chain_id: Option<u64>,
+    allowed_chain_ids: &[u64],
+    i_know_this_is_mainnet: bool,
+) -> anyhow::Result<u64> {
+    let chain_id = get_chain_id(endpoints.primary()).await?;
+    println!("chain id: {}", chain_id);
+
+    if let Some(network_chain_id) = network_chain_id {
+        if network_chain_id != chain_id {
+            bail!(
+                "{} is on chain id {}, the network expects chain id {}",
+                endpoints.primary(),
+                chain_id,
+                network_chain_id
+            );
+        }
+    }
+
+    for rpc_url in endpoints.urls().skip(1) {
+        let other = get_chain_id(rpc_url).await?;
+        if other != chain_id {
+            bail!(
+                "{} is on chain id {}, {} is on chain id {}",
+                rpc_url,
+                other,
+                endpoints.primary(),
+                chain_id
+            );
+        }
+    }
+
+    // a network profile which names its chain id allows it
+    if !allowed_chain_ids.contains(&chain_id) && network_chain_id != Some(chain_id) {
+        if !i_know_this_is_mainnet {
+            bail!(
+                "chain id {} is not allowed, add it with --allow-chain-id or pass --i-know-this-is-mainnet",
+                chain_id
+            );
+        }
+        println!(
+            "chain id {} is not allowed, continue because of --i-know-this-is-mainnet",
+            chain_id
+        );
+    }
+
+    Ok(chain_id)
+}
+
+// the address of a `@name` reference in the address book of the node's chain
+async fn resolve_contract_addr(
+    contract_addr: &str,
+    rpc_url: &str,
+    address_book: Option<PathBuf>,
+    network: Option<&str>,
+) -> anyhow::Result<String> {
+    if !contract_addr.starts_with('@') {
+        return Ok(contract_addr.to_string());
+    }
+
+    let chain_id = get_chain_id(rpc_url).await?;
+    let path = address_book_path(address_book, network, chain_id);
+    AddressBook::open(path).await?.resolve(contract_addr)
+}
+
+fn print_task_stat(success_task: u32, total_times: u128) {
+    println!(
+        "success task: {} total times: {} average time: {}",
+        success_task,
+        total_times,
+        if success_task == 0 {
+            0
+        } else {
+            total_times / success_task as u128
+        }
+    );
+}
@@ -1,56 +1,197 @@
 // use crate::get_timestamp;
 
+use crate::{
+    endpoints::{Endpoints, Lease},
+    load_profile::parse_secs,
+    rate_limit::Limiter,
+};
 use anyhow;
 use lazy_static::lazy_static;
 use std::{
+    cell::Cell,
+    collections::{HashMap, VecDeque},
     future::Future,
     sync::{
-        atomic::{AtomicU32, Ordering},
+        atomic::{AtomicU32, AtomicU64, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
+use structopt::StructOpt;
 use tokio::sync::{mpsc::Receiver, Mutex};
+use web3::types::H160;
 
 lazy_static! {
     pub(crate) static ref CUR_TASKS: Arc<AtomicU32> = Arc::new(AtomicU32::new(0));
-    pub(crate) static ref MAX_TASKS: Arc<AtomicU32> = Arc::new(AtomicU32::new(2));
+    pub(crate) static ref MAX_TASKS: Arc<AtomicU32> = Arc::new(AtomicU32::new(INITIAL_MAX_TASKS));
     // total success tasks、total tasks cost time、average tasks cost time queue
     pub(crate) static ref RES_QUEUE_SECS: Arc<Mutex<(u32, u128, Vec::<u128>)>> = Arc::new(Mutex::new((0, 0, Vec::new())));
 
 }
 const RES_QUEUE_MAX_LEN: usize = 10;
+const INITIAL_MAX_TASKS: u32 = 2;
 const UPDATE_INTERVAL: u64 = 300;
 const DELTA_RANGE: u128 = 100;
+// how often a paced run checks for a free slot under its ceiling
+const PACED_POLL_MS: u64 = 5;
+
+// warm-up tasks of the run, the run stats are reset together in `reset_run_stats`
+static WARMUP_TASKS: AtomicU32 = AtomicU32::new(0);
+// tasks of the run which waited for receipts and the milliseconds they waited
+static CONFIRMED_TASKS: AtomicU32 = AtomicU32::new(0);
+static CONFIRM_MS: AtomicU64 = AtomicU64::new(0);
+
+tokio::task_local! {
+    // milliseconds the running task waited for receipts
+    static TASK_CONFIRM_MS: Cell<u64>;
+}
+
+// receipt waits of the running task count as confirm time instead of task time
+pub(crate) fn add_confirm_ms(millis: u64) {
+    let _ = TASK_CONFIRM_MS.try_with(|ms| ms.set(ms.get() + millis));
+}
+
+pub(crate) fn task_confirm_ms() -> u64 {
+    TASK_CONFIRM_MS.try_with(|ms| ms.get()).unwrap_or(0)
+}
+
+#[derive(StructOpt, Debug, Clone, Copy, Default)]
+pub(crate) struct Warmup {
+    /// tasks started in this first part of the run, like 30s, run but stay out of the stats
+    #[structopt(name = "warmup", long = "warmup", parse(try_from_str = parse_secs))]
+    pub(crate) secs: Option<f64>,
+
+    /// the first tasks of the run, which run but stay out of the stats
+    #[structopt(name = "warmup count", long = "warmup-count")]
+    pub(crate) count: Option<u32>,
+}
+
+impl Warmup {
+    // whether the `started`th task (from 0), started `secs` into the run, warms up
+    fn covers(&self, started: u32, secs: f64) -> bool {
+        self.count.is_some_and(|count| started < count)
+            || self.secs.is_some_and(|warmup| secs < warmup)
+    }
+}
+
+// tasks queued per sender in their order, the next task to start is the first one of the sender
+// whose limit frees up first, so a sender out of tokens doesn't hold back the others
+struct SenderQueues<F> {
+    queues: Vec<SenderQueue<F>>,
+}
+
+// the sender, none for tasks which send nothing, and its tasks with their place in the run
+type SenderQueue<F> = (Option<H160>, VecDeque<(usize, F)>);
+
+impl<F> SenderQueues<F> {
+    fn new(vf: Vec<(Option<H160>, F)>) -> Self {
+        let mut queues: Vec<SenderQueue<F>> = Vec::new();
+        let mut queue_of = HashMap::new();
+        for (index, (sender, f)) in vf.into_iter().enumerate() {
+            let queue = *queue_of.entry(sender).or_insert_with(|| {
+                queues.push((sender, VecDeque::new()));
+                queues.len() - 1
+            });
+            queues[queue].1.push_back((index, f));
+        }
+
+        SenderQueues { queues }
+    }
+
+    // without sender limits the tasks come in their order
+    fn next(&mut self, limiter: &Limiter) -> Option<(Option<H160>, F)> {
+        let (sender, tasks) = self
+            .queues
+            .iter_mut()
+            .filter(|(_, tasks)| !tasks.is_empty())
+            .min_by_key(|(sender, tasks)| (limiter.sender_ready_in(*sender), tasks[0].0))?;
+
+        tasks.pop_front().map(|(_, f)| (*sender, f))
+    }
+}
 
-pub(crate) async fn multi_tasks_impl<F, T>(vf: Vec<F>) -> anyhow::Result<(u32, u128)>
+// tasks with their sender, none for tasks which send nothing, each gets the endpoint it runs on once it may start
+pub(crate) async fn multi_tasks_impl<F, T>(
+    vf: Vec<(Option<H160>, F)>,
+    endpoints: Arc<Endpoints>,
+    limiter: Arc<Limiter>,
+    warmup: Warmup,
+) -> anyhow::Result<(u32, u128)>
 where
-    F: FnOnce() -> T,
+    F: FnOnce(Lease) -> T,
     T: Future<Output = anyhow::Result<()>> + Send + 'static,
 {
+    let paced = limiter.pacer().is_some();
+    if let Some(pacer) = limiter.pacer() {
+        pacer.reset();
+    }
+    let run_start = Instant::now();
+    reset_run_stats().await;
+
     let mut task_queue = Vec::with_capacity(vf.len());
-    for f in vf {
-        let af = f();
-        let task = tokio::spawn(async move {
-            CUR_TASKS.store(CUR_TASKS.load(Ordering::Acquire) + 1, Ordering::Release);
+    let mut queues = SenderQueues::new(vf);
+    let mut started = 0;
+    while let Some((sender, f)) = queues.next(&limiter) {
+        // under a load profile only its ceiling, or its concurrency with that target, holds
+        // back the next task
+        if let Some(pacer) = limiter.pacer() {
+            while pacer.max_in_flight() <= CUR_TASKS.load(Ordering::Acquire) {
+                tokio::time::sleep(Duration::from_millis(PACED_POLL_MS)).await;
+            }
+        }
+        // the rate limits are waited for here, so tasks waiting for them don't run past the
+        // concurrency limit and their wait stays out of the task time
+        limiter.acquire(sender).await;
+        // warm-up tasks run like the others but stay out of the stats, decided before the task is
+        // made so its endpoint and retries know it too
+        let warming = warmup.covers(started, run_start.elapsed().as_secs_f64());
+        started += 1;
+        if warming {
+            WARMUP_TASKS.fetch_add(1, Ordering::AcqRel);
+        }
+        let af = f(endpoints.lease(warming).await);
+        CUR_TASKS.fetch_add(1, Ordering::AcqRel);
 
+        let limiter = limiter.clone();
+        let task = tokio::spawn(async move {
+            let phase = limiter.pacer().map(|pacer| pacer.phase());
             let beg_time = get_timestamp();
 
-            match af.await {
-                Ok(_) => {
-                    let end_time = get_timestamp();
-                    update_res_queue_secs(end_time - beg_time).await;
+            let (res, confirm_ms) = TASK_CONFIRM_MS
+                .scope(Cell::new(0), async move { (af.await, task_confirm_ms()) })
+                .await;
+            let end_time = get_timestamp();
+            // the time the node took, the wait for blocks is reported on its own
+            let task_ms = (end_time - beg_time).saturating_sub(confirm_ms as u128);
+            match res {
+                Ok(_) if !warming => {
+                    update_res_queue_secs(task_ms).await;
+                    if confirm_ms > 0 {
+                        CONFIRMED_TASKS.fetch_add(1, Ordering::AcqRel);
+                        CONFIRM_MS.fetch_add(confirm_ms, Ordering::AcqRel);
+                    }
                 }
-                Err(_) => {}
+                _ => {}
             };
-            CUR_TASKS.store(CUR_TASKS.load(Ordering::Acquire) - 1, Ordering::Release);
+            match (limiter.pacer(), phase) {
+                (Some(pacer), Some(phase)) if !warming => {
+                    pacer.record(phase, res.is_ok(), task_ms as u64)
+                }
+                _ => {}
+            }
+            CUR_TASKS.fetch_sub(1, Ordering::AcqRel);
         });
         task_queue.push(task);
 
-        while MAX_TASKS.load(Ordering::Acquire) <= CUR_TASKS.load(Ordering::Acquire) {
+        // a load profile sets the pace, slow tasks must not hold back the ones due
+        while !paced && MAX_TASKS.load(Ordering::Acquire) <= CUR_TASKS.load(Ordering::Acquire) {
             let task = task_queue.pop().unwrap();
             task.await?;
         }
     }
+    if let Some(pacer) = limiter.pacer() {
+        pacer.check_end();
+    }
 
     let (tx1, rx1) = tokio::sync::mpsc::channel(2);
     tokio::spawn(max_tasks_update(rx1));
@@ -70,6 +211,34 @@ where
     return anyhow::Ok((success_task, total_times));
 }
 
+// the stats and the adaptive concurrency limit start over with each run, a scenario runs one per
+// step
+async fn reset_run_stats() {
+    *RES_QUEUE_SECS.lock().await = (0, 0, Vec::new());
+    MAX_TASKS.store(INITIAL_MAX_TASKS, Ordering::Release);
+    WARMUP_TASKS.store(0, Ordering::Release);
+    CONFIRMED_TASKS.store(0, Ordering::Release);
+    CONFIRM_MS.store(0, Ordering::Release);
+}
+
+pub(crate) fn print_confirm_stat() {
+    let confirmed_tasks = CONFIRMED_TASKS.load(Ordering::Acquire);
+    if confirmed_tasks > 0 {
+        println!(
+            "confirmed tasks: {} average confirm time: {}, not in the task times",
+            confirmed_tasks,
+            CONFIRM_MS.load(Ordering::Acquire) / confirmed_tasks as u64
+        );
+    }
+}
+
+pub(crate) fn print_warmup_stat() {
+    let warmup_tasks = WARMUP_TASKS.load(Ordering::Acquire);
+    if warmup_tasks > 0 {
+        println!("warm-up tasks: {}, not in the stats", warmup_tasks);
+    }
+}
+
 async fn max_tasks_update(mut rx: Receiver<()>) {
     loop {
         let res_queue_secs = RES_QUEUE_SECS.lock().await;
@@ -139,3 +308,35 @@ fn get_timestamp() -> u128 {
         Err(_) => panic!("SystemTime before UNIX EPOCH!"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{load_profile::LoadTarget, rate_limit::RateLimits};
+
+    #[tokio::test]
+    async fn sender_queues() {
+        let limiter = Limiter::new(&RateLimits {
+            rate: None,
+            endpoint_rate: None,
+            sender_rate: Some(10.0),
+            burst: 1,
+            load_profile: None,
+            load_target: LoadTarget::Rate,
+            max_in_flight: 1000,
+        })
+        .unwrap();
+        let (a, b) = (H160::repeat_byte(1), H160::repeat_byte(2));
+        let (a, b) = (Some(a), Some(b));
+        let mut queues = SenderQueues::new(vec![(a, 0), (a, 1), (b, 2), (None, 3), (b, 4)]);
+
+        // a's second task waits for its token, b's tasks and the one without a sender don't wait
+        // behind it
+        let mut order = Vec::new();
+        while let Some((sender, task)) = queues.next(&limiter) {
+            limiter.acquire(sender).await;
+            order.push(task);
+        }
+        assert_eq!(order, [0, 2, 3, 1, 4]);
+    }
+}
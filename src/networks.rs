@@ -0,0 +1,146 @@
+use crate::{
+    commands::{check_gas_multiplier, GasValue, TxType},
+    task_impl::TxParams,
+};
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+const NETWORKS_ENV: &str = "PLATFORM_TOOL_NETWORKS";
+
+// one table of networks.toml:
+//   [devnet]
+//   rpc_urls = ["http://172.25.210.112:9999"]
+//   chain_id = 2152
+//   explorer = "http://172.25.210.112:8080"
+//   gas_price = "10gwei"
+//   [devnet.keys]
+//   deployer = "keystore:deployer.json"
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Network {
+    #[serde(default)]
+    pub(crate) rpc_urls: Vec<String>,
+    // checked against the node before sending transactions
+    pub(crate) chain_id: Option<u64>,
+    pub(crate) explorer: Option<String>,
+    pub(crate) gas_price: Option<GasValue>,
+    pub(crate) max_fee_per_gas: Option<GasValue>,
+    pub(crate) max_priority_fee_per_gas: Option<GasValue>,
+    pub(crate) tx_type: Option<TxType>,
+    pub(crate) gas_multiplier: Option<f64>,
+    // key references by name, used as `key:<name>`
+    #[serde(default)]
+    pub(crate) keys: HashMap<String, String>,
+}
+
+// networks.toml from PLATFORM_TOOL_NETWORKS or the user config directory
+fn networks_path() -> anyhow::Result<PathBuf> {
+    if let Some(path) = env::var_os(NETWORKS_ENV) {
+        return Ok(path.into());
+    }
+
+    let config_dir = if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(dir) = env::var_os("APPDATA") {
+        PathBuf::from(dir)
+    } else if let Some(home) = env::var_os("HOME") {
+        Path::new(&home).join(".config")
+    } else {
+        bail!("no config directory, set {}", NETWORKS_ENV);
+    };
+
+    Ok(config_dir.join("platform-tool").join("networks.toml"))
+}
+
+// the network named by --network, empty without one
+pub(crate) fn select(name: Option<&str>) -> anyhow::Result<Network> {
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(Network::default()),
+    };
+
+    let path = networks_path()?;
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| anyhow!("read networks file {:?} failed: {}", path, e))?;
+    let mut networks: HashMap<String, Network> = toml::from_str(&content)
+        .map_err(|e| anyhow!("parse networks file {:?} failed: {}", path, e))?;
+    let network = match networks.remove(name) {
+        Some(network) => network,
+        None => {
+            let mut names: Vec<_> = networks.keys().cloned().collect();
+            names.sort();
+            bail!(
+                "no network {} in {:?}, found {}",
+                name,
+                path,
+                names.join(", ")
+            );
+        }
+    };
+    if let Some(multiplier) = network.gas_multiplier {
+        check_gas_multiplier(multiplier)
+            .map_err(|e| anyhow!("network {} in {:?}: {}", name, path, e))?;
+    }
+
+    Ok(network)
+}
+
+impl Network {
+    // the key reference behind `key:<name>`, other references are returned as they are
+    pub(crate) fn expand_key_ref(&self, key_ref: &str) -> anyhow::Result<String> {
+        match key_ref.strip_prefix("key:") {
+            Some(name) => match self.keys.get(name) {
+                Some(key_ref) => Ok(key_ref.clone()),
+                None => bail!("no key {} in the network, pick one with --network", name),
+            },
+            None => Ok(key_ref.to_string()),
+        }
+    }
+
+    pub(crate) fn rpc_url(&self, rpc_url: Option<String>) -> anyhow::Result<String> {
+        match rpc_url.or_else(|| self.rpc_urls.first().cloned()) {
+            Some(rpc_url) => Ok(rpc_url),
+            None => bail!("--rpc-url or --network is required"),
+        }
+    }
+
+    // the command line urls, the network ones when there are none and no --rpc-list
+    pub(crate) fn rpc_urls(
+        &self,
+        rpc_urls: Vec<String>,
+        rpc_list: &Option<PathBuf>,
+    ) -> Vec<String> {
+        if rpc_urls.is_empty() && rpc_list.is_none() {
+            self.rpc_urls.clone()
+        } else {
+            rpc_urls
+        }
+    }
+
+    // gas prices and fees of the network for the ones neither the command line nor the config
+    // sets, the gas limit depends on the operation so it is never taken from the network
+    pub(crate) fn gas_defaults(&self, tx_params: TxParams) -> TxParams {
+        TxParams {
+            gas_price: tx_params.gas_price.or_else(|| self.gas_price.clone()),
+            max_fee_per_gas: tx_params
+                .max_fee_per_gas
+                .or_else(|| self.max_fee_per_gas.clone()),
+            max_priority_fee_per_gas: tx_params
+                .max_priority_fee_per_gas
+                .or_else(|| self.max_priority_fee_per_gas.clone()),
+            ..tx_params
+        }
+    }
+
+    // explorer page of a transaction or an address, like "<explorer>/tx/0x.."
+    pub(crate) fn explorer_link(&self, kind: &str, value: &str) -> Option<String> {
+        self.explorer
+            .as_ref()
+            .map(|explorer| format!("{}/{}/{}", explorer.trim_end_matches('/'), kind, value))
+    }
+}
@@ -0,0 +1,178 @@
+use crate::{
+    commands::{GasValue, TxType},
+    task_impl::{auto_max_fee, TxParams},
+    transport::{connect, AnyTransport},
+};
+use anyhow::bail;
+use std::collections::BTreeMap;
+use web3::{
+    helpers::CallFuture,
+    types::{BlockId, BlockNumber, H160, U256},
+    Transport, Web3,
+};
+
+// one transaction the run is going to send
+pub(crate) struct PlannedTx {
+    pub(crate) from: H160,
+    // gas limit used when gas is not fixed in the config
+    pub(crate) default_gas: u64,
+    pub(crate) tx_params: TxParams,
+}
+
+// prices of the node, taken once for the whole run
+#[derive(Default)]
+struct NodePrices {
+    gas_price: U256,
+    // set when a 1559 transaction has an "auto" max fee
+    base_fee: Option<U256>,
+    max_priority_fee_per_gas: U256,
+}
+
+// check every sender can pay for the worst case of the whole run before sending anything,
+// `earlier` is the worst case planned before in the same run, like by earlier scenario steps,
+// which counts toward the max spend. Returns the worst case of `planned`
+pub(crate) async fn check_spend(
+    rpc_url: &str,
+    planned: &[PlannedTx],
+    max_spend: Option<U256>,
+    earlier: U256,
+) -> anyhow::Result<U256> {
+    // estimated gas is only known when the transaction is sent, so it can't be capped
+    if max_spend.is_some()
+        && planned
+            .iter()
+            .any(|tx| matches!(tx.tx_params.gas, Some(GasValue::Auto)))
+    {
+        bail!("gas \"auto\" can't be checked against --max-spend, set a fixed gas instead");
+    }
+
+    let web3 = connect(rpc_url).await?;
+    let prices = node_prices(&web3, planned).await?;
+
+    let mut required: BTreeMap<H160, U256> = BTreeMap::new();
+    for tx in planned {
+        let cost = worst_case_cost(&tx.tx_params, tx.default_gas, &prices);
+        let sum = required.entry(tx.from).or_default();
+        *sum = sum.saturating_add(cost);
+    }
+
+    let total = required
+        .values()
+        .fold(U256::zero(), |acc, v| acc.saturating_add(*v));
+    println!(
+        "worst-case spend: {} wei from {} accounts",
+        total,
+        required.len()
+    );
+
+    if let Some(max_spend) = max_spend {
+        if earlier.is_zero() && total > max_spend {
+            bail!(
+                "worst-case spend {} wei exceeds --max-spend {} wei",
+                total,
+                max_spend
+            );
+        } else if total.saturating_add(earlier) > max_spend {
+            bail!(
+                "worst-case spend {} wei and {} wei planned before exceed --max-spend {} wei",
+                total,
+                earlier,
+                max_spend
+            );
+        }
+    }
+
+    let mut shortfalls = Vec::new();
+    for (account, required) in required {
+        let balance = web3.eth().balance(account, None).await?;
+        if balance < required {
+            shortfalls.push((account, balance, required));
+        }
+    }
+
+    if !shortfalls.is_empty() {
+        println!(
+            "{:<44} {:>28} {:>28} {:>28}",
+            "account", "balance", "required", "shortfall"
+        );
+        for (account, balance, required) in &shortfalls {
+            println!(
+                "{:<44} {:>28} {:>28} {:>28}",
+                format!("{:?}", account),
+                balance,
+                required,
+                required - balance
+            );
+        }
+        bail!("{} accounts have insufficient funds", shortfalls.len());
+    }
+
+    Ok(total)
+}
+
+// "auto" and unset prices are taken from the node the way the transactions take them
+async fn node_prices(
+    web3: &Web3<AnyTransport>,
+    planned: &[PlannedTx],
+) -> anyhow::Result<NodePrices> {
+    let mut prices = NodePrices {
+        gas_price: web3.eth().gas_price().await?,
+        ..Default::default()
+    };
+
+    let auto_max_fee: Vec<&TxParams> = planned
+        .iter()
+        .map(|tx| &tx.tx_params)
+        .filter(|tx_params| {
+            tx_params.tx_type == TxType::DynamicFee
+                && matches!(tx_params.max_fee_per_gas, Some(GasValue::Auto))
+        })
+        .collect();
+    if auto_max_fee.is_empty() {
+        return Ok(prices);
+    }
+    let block = web3
+        .eth()
+        .block(BlockId::Number(BlockNumber::Latest))
+        .await?;
+    prices.base_fee = block.and_then(|b| b.base_fee_per_gas);
+    if auto_max_fee
+        .iter()
+        .any(|tx_params| matches!(tx_params.max_priority_fee_per_gas, Some(GasValue::Auto)))
+    {
+        prices.max_priority_fee_per_gas =
+            CallFuture::new(web3.transport().execute("eth_maxPriorityFeePerGas", vec![])).await?;
+    }
+
+    Ok(prices)
+}
+
+fn worst_case_cost(tx_params: &TxParams, default_gas: u64, prices: &NodePrices) -> U256 {
+    let gas = match tx_params.gas {
+        Some(GasValue::Fixed(gas)) => gas,
+        // estimated gas is not known before sending, use the default limit instead
+        _ => default_gas.into(),
+    };
+    let price = match tx_params.tx_type {
+        TxType::DynamicFee => match (&tx_params.max_fee_per_gas, prices.base_fee) {
+            (Some(GasValue::Fixed(max_fee)), _) => *max_fee,
+            (Some(GasValue::Auto), Some(base_fee)) => {
+                let max_priority_fee_per_gas = match tx_params.max_priority_fee_per_gas {
+                    Some(GasValue::Fixed(fee)) => fee,
+                    Some(GasValue::Auto) => prices.max_priority_fee_per_gas,
+                    None => U256::zero(),
+                };
+                auto_max_fee(base_fee, max_priority_fee_per_gas)
+            }
+            // without a base fee the node gas price is used, like unset fees
+            _ => prices.gas_price,
+        },
+        _ => match tx_params.gas_price {
+            Some(GasValue::Fixed(gas_price)) => gas_price,
+            _ => prices.gas_price,
+        },
+    };
+
+    gas.saturating_mul(price)
+        .saturating_add(tx_params.value.unwrap_or_default())
+}
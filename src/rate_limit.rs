@@ -0,0 +1,194 @@
+use crate::load_profile::{LoadProfile, LoadTarget, Pacer};
+use anyhow::bail;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use structopt::StructOpt;
+use web3::types::H160;
+
+#[derive(StructOpt, Debug, Clone)]
+pub(crate) struct RateLimits {
+    /// tasks started per second over the whole run
+    #[structopt(name = "rate", long = "rate")]
+    pub(crate) rate: Option<f64>,
+
+    /// tasks started per second on each rpc endpoint, a rate in the --rpc-list file overrides it
+    #[structopt(name = "endpoint rate", long = "endpoint-rate")]
+    pub(crate) endpoint_rate: Option<f64>,
+
+    /// tasks started per second by each sending account
+    #[structopt(name = "sender rate", long = "sender-rate")]
+    pub(crate) sender_rate: Option<f64>,
+
+    /// tasks which may start at once under each rate limit
+    #[structopt(name = "burst", long = "burst", default_value = "1")]
+    pub(crate) burst: u32,
+
+    /// start rate changing over time, with a report per phase: ramp:<from>:<to>:<duration>,
+    /// steps:<rate>,<rate>,..:<duration of each>, spike:<base>:<peak>:<at>:<duration> or a csv
+    /// file of "<second>,<rate>" lines
+    #[structopt(name = "load profile", long = "load-profile")]
+    pub(crate) load_profile: Option<LoadProfile>,
+
+    /// what the rates of the load profile set: rate, tasks started per second, or concurrency,
+    /// tasks running at once
+    #[structopt(name = "load target", long = "load-target", default_value = "rate")]
+    pub(crate) load_target: LoadTarget,
+
+    /// most tasks running at once under a load profile
+    #[structopt(name = "max in flight", long = "max-in-flight", default_value = "1000")]
+    pub(crate) max_in_flight: u32,
+}
+
+// `rate` tokens a second, holding up to `burst` of them
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    // tokens left at the instant, negative when callers are already waiting for them
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: f64, burst: u32) -> anyhow::Result<Self> {
+        if rate.is_nan() || rate <= 0.0 {
+            bail!("rate limit {} should be above 0", rate);
+        }
+        let burst = burst.max(1) as f64;
+
+        Ok(TokenBucket {
+            rate,
+            burst,
+            state: Mutex::new((burst, Instant::now())),
+        })
+    }
+
+    // takes a token right away and sleeps until it is due, so waiters go in order
+    pub(crate) async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+            let elapsed = now.duration_since(state.1).as_secs_f64();
+            let tokens = (state.0 + elapsed * self.rate).min(self.burst);
+            *state = (tokens - 1.0, now);
+            if tokens >= 1.0 {
+                return;
+            }
+            Duration::from_secs_f64((1.0 - tokens) / self.rate)
+        };
+
+        tokio::time::sleep(wait).await;
+    }
+
+    // how long until a token is free, without taking it
+    pub(crate) fn ready_in(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        let elapsed = state.1.elapsed().as_secs_f64();
+        let tokens = (state.0 + elapsed * self.rate).min(self.burst);
+        if tokens >= 1.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((1.0 - tokens) / self.rate)
+    }
+}
+
+// global and per sender limits and the load profile, the endpoint limits live with the endpoints
+pub(crate) struct Limiter {
+    global: Option<TokenBucket>,
+    pacer: Option<Pacer>,
+    sender_rate: Option<f64>,
+    burst: u32,
+    senders: Mutex<HashMap<H160, Arc<TokenBucket>>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: &RateLimits) -> anyhow::Result<Self> {
+        let global = match limits.rate {
+            Some(rate) => Some(TokenBucket::new(rate, limits.burst)?),
+            None => None,
+        };
+        if let Some(rate) = limits.sender_rate {
+            TokenBucket::new(rate, limits.burst)?;
+        }
+        if limits.max_in_flight == 0 {
+            bail!("--max-in-flight should be above 0");
+        }
+
+        Ok(Limiter {
+            global,
+            pacer: limits
+                .load_profile
+                .clone()
+                .map(|profile| Pacer::new(profile, limits.load_target, limits.max_in_flight)),
+            sender_rate: limits.sender_rate,
+            burst: limits.burst,
+            senders: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // wait until a task of `sender` may start, a task without a sender has no sender limit
+    pub(crate) async fn acquire(&self, sender: Option<H160>) {
+        if let (Some(rate), Some(sender)) = (self.sender_rate, sender) {
+            let bucket = self
+                .senders
+                .lock()
+                .unwrap()
+                .entry(sender)
+                .or_insert_with(|| Arc::new(TokenBucket::new(rate, self.burst).unwrap()))
+                .clone();
+            bucket.acquire().await;
+        }
+        if let Some(global) = &self.global {
+            global.acquire().await;
+        }
+        if let Some(pacer) = &self.pacer {
+            pacer.wait().await;
+        }
+    }
+
+    // how long until a task of `sender` is free of its sender limit
+    pub(crate) fn sender_ready_in(&self, sender: Option<H160>) -> Duration {
+        match sender.and_then(|sender| self.senders.lock().unwrap().get(&sender).cloned()) {
+            Some(bucket) => bucket.ready_in(),
+            None => Duration::ZERO,
+        }
+    }
+
+    // tasks start on the schedule of the load profile instead of the adaptive concurrency limit
+    pub(crate) fn pacer(&self) -> Option<&Pacer> {
+        self.pacer.as_ref()
+    }
+
+    pub(crate) fn print_phase_stats(&self) {
+        if let Some(pacer) = &self.pacer {
+            pacer.print_stats();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn token_bucket() {
+        let bucket = TokenBucket::new(50.0, 2).unwrap();
+        let beg = Instant::now();
+        // the burst right away, then one every 20ms
+        bucket.acquire().await;
+        assert_eq!(bucket.ready_in(), Duration::ZERO);
+        bucket.acquire().await;
+        assert!(beg.elapsed() < Duration::from_millis(15));
+        assert!(bucket.ready_in() > Duration::from_millis(10));
+        for _ in 0..5 {
+            bucket.acquire().await;
+        }
+        let elapsed = beg.elapsed();
+        assert!(elapsed >= Duration::from_millis(95), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(500), "{:?}", elapsed);
+
+        assert!(TokenBucket::new(0.0, 1).is_err());
+        assert!(TokenBucket::new(f64::NAN, 1).is_err());
+    }
+}
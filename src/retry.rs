@@ -0,0 +1,252 @@
+use crate::load_profile::parse_secs;
+use rand::Rng;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+use structopt::StructOpt;
+use web3::{contract, error::TransportError, Error};
+
+const MAX_BACKOFF_MS: u64 = 30_000;
+// node answers worth another attempt, matched in lowercase
+const TRANSIENT_MESSAGES: [&str; 6] = [
+    "txpool is full",
+    "transaction pool is full",
+    "too many requests",
+    "rate limit",
+    "limit exceeded",
+    "header not found",
+];
+// transport messages of a failed connection, as web3 and the WebSocket transport word them,
+// matched in lowercase, an answer which doesn't parse or a bad url or certificate is not one
+const CONNECTION_MESSAGES: [&str; 7] = [
+    "failed to send request",
+    "failed to read response bytes",
+    "send error",
+    "recv error",
+    "websocket connection closed",
+    "websocket handshake error",
+    "subscription closed",
+];
+// answers to a resent transaction which may mean the first send went through
+const ALREADY_SENT_MESSAGES: [&str; 4] = [
+    "nonce too low",
+    "already known",
+    "known transaction",
+    "already imported",
+];
+
+// totals over all tasks of the run
+static RETRIES: AtomicU32 = AtomicU32::new(0);
+static RETRIED_TASKS: AtomicU32 = AtomicU32::new(0);
+
+#[derive(StructOpt, Debug, Clone, Copy)]
+pub(crate) struct RetryPolicy {
+    /// attempts of each step of a task on transport errors, HTTP 429/503 and a full txpool, 1 disables retries
+    #[structopt(name = "max attempts", long = "max-attempts", default_value = "3")]
+    pub(crate) max_attempts: u32,
+
+    /// delay before the first retry in milliseconds, doubled on each retry
+    #[structopt(name = "retry backoff", long = "retry-backoff", default_value = "500")]
+    pub(crate) backoff_ms: u64,
+
+    /// random part of the retry delay, 0.2 waits between 80% and 120% of it
+    #[structopt(name = "retry jitter", long = "retry-jitter", default_value = "0.2")]
+    pub(crate) jitter: f64,
+
+    /// time to wait for the receipt of a sent transaction before its task fails, like 2m
+    #[structopt(
+        name = "receipt timeout",
+        long = "receipt-timeout",
+        default_value = "120s",
+        parse(try_from_str = parse_secs)
+    )]
+    pub(crate) receipt_timeout: f64,
+}
+
+impl RetryPolicy {
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .backoff_ms
+            .saturating_mul(1 << retry.min(16))
+            .min(MAX_BACKOFF_MS) as f64;
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter);
+
+        Duration::from_millis((backoff * factor) as u64)
+    }
+}
+
+// errors which carry the node or transport error behind them
+pub(crate) trait RpcError {
+    fn web3_error(&self) -> Option<&Error>;
+}
+
+impl RpcError for Error {
+    fn web3_error(&self) -> Option<&Error> {
+        Some(self)
+    }
+}
+
+impl RpcError for contract::Error {
+    fn web3_error(&self) -> Option<&Error> {
+        match self {
+            contract::Error::Api(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// task errors which carry a web3 one, a bad address or a missing abi file carries none
+impl RpcError for anyhow::Error {
+    fn web3_error(&self) -> Option<&Error> {
+        match self.downcast_ref::<contract::Error>() {
+            Some(e) => e.web3_error(),
+            None => self.downcast_ref::<Error>(),
+        }
+    }
+}
+
+// connection failures, rate limits and a full txpool, never a revert or a rejected transaction
+pub(crate) fn is_transient<E: RpcError>(e: &E) -> bool {
+    match e.web3_error() {
+        Some(Error::Unreachable) | Some(Error::Io(_)) => true,
+        Some(Error::Transport(TransportError::Code(code))) => {
+            matches!(code, 429 | 502 | 503 | 504)
+        }
+        Some(Error::Transport(TransportError::Message(message))) => {
+            let message = message.to_lowercase();
+            CONNECTION_MESSAGES.iter().any(|m| message.contains(m))
+        }
+        Some(Error::Rpc(e)) => {
+            let message = e.message.to_lowercase();
+            e.code.code() == -32005 || TRANSIENT_MESSAGES.iter().any(|m| message.contains(m))
+        }
+        _ => false,
+    }
+}
+
+pub(crate) fn is_already_sent<E: RpcError>(e: &E) -> bool {
+    match e.web3_error() {
+        Some(Error::Rpc(e)) => {
+            let message = e.message.to_lowercase();
+            ALREADY_SENT_MESSAGES.iter().any(|m| message.contains(m))
+        }
+        _ => false,
+    }
+}
+
+// retries of one task, each step gets `max_attempts` on its own
+pub(crate) struct Retry {
+    policy: RetryPolicy,
+    retries: AtomicU32,
+    // retries of warm-up tasks stay out of the stats
+    warming: bool,
+}
+
+impl Retry {
+    pub(crate) fn new(policy: RetryPolicy, warming: bool) -> Self {
+        Retry {
+            policy,
+            retries: AtomicU32::new(0),
+            warming,
+        }
+    }
+
+    pub(crate) fn receipt_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.policy.receipt_timeout)
+    }
+
+    // " after <n> retries" for the result line of the task, empty without retries
+    pub(crate) fn note(&self) -> String {
+        match self.retries.load(Ordering::Acquire) {
+            0 => String::new(),
+            1 => " after 1 retry".to_string(),
+            n => format!(" after {} retries", n),
+        }
+    }
+
+    // waits the backoff and returns true when attempt `attempt` (from 0) failing with `e` is
+    // worth another one
+    pub(crate) async fn again<E: RpcError>(&self, attempt: u32, e: &E) -> bool {
+        if attempt + 1 >= self.policy.max_attempts || !is_transient(e) {
+            return false;
+        }
+
+        if self.retries.fetch_add(1, Ordering::AcqRel) == 0 && !self.warming {
+            RETRIED_TASKS.fetch_add(1, Ordering::AcqRel);
+        }
+        if !self.warming {
+            RETRIES.fetch_add(1, Ordering::AcqRel);
+        }
+        tokio::time::sleep(self.policy.delay(attempt)).await;
+
+        true
+    }
+
+    // run a step which is safe to repeat, like signing or waiting for a receipt
+    pub(crate) async fn run<F, Fut, R, E>(&self, mut f: F) -> Result<R, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<R, E>>,
+        E: RpcError,
+    {
+        let mut attempt = 0;
+        loop {
+            match f().await {
+                Ok(v) => return Ok(v),
+                Err(e) if self.again(attempt, &e).await => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+pub(crate) fn print_retry_stat() {
+    let retries = RETRIES.load(Ordering::Acquire);
+    if retries > 0 {
+        println!(
+            "retries: {} in {} tasks",
+            retries,
+            RETRIED_TASKS.load(Ordering::Acquire)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(message: &str) -> Error {
+        Error::Transport(TransportError::Message(message.to_string()))
+    }
+
+    #[test]
+    fn transient_by_cause() {
+        assert!(is_transient(&message(
+            "failed to send request: error sending request for url (http://127.0.0.1:1/)"
+        )));
+        assert!(is_transient(&message("WebSocket connection closed")));
+        assert!(is_transient(&Error::Transport(TransportError::Code(503))));
+        assert!(is_transient(&Error::Unreachable));
+
+        assert!(!is_transient(&message(
+            "failed to deserialize response: expected value: <html>"
+        )));
+        assert!(!is_transient(&message("TLS error: invalid certificate")));
+        assert!(!is_transient(&Error::Transport(TransportError::Code(401))));
+        assert!(!is_transient(&Error::InvalidResponse(
+            "no receipt within 120s".to_string()
+        )));
+    }
+
+    #[test]
+    fn transient_task_errors() {
+        assert!(is_transient(&anyhow::Error::from(Error::Unreachable)));
+        assert!(is_transient(&anyhow::Error::from(contract::Error::Api(
+            Error::Unreachable
+        ))));
+        assert!(!is_transient(&anyhow::anyhow!("read abi.json failed")));
+    }
+}
@@ -0,0 +1,879 @@
+use crate::{
+    address_book::{file_stem, AddressBook, Deployed, Deployment},
+    commands::{
+        parse_amount, parse_args_csv, CallJsonObj, DeployJsonObj, GasValue, QueryJson,
+        TransferJsonObj, TxType,
+    },
+    endpoints::{Endpoints, Lease},
+    keys::resolve_keys,
+    load_profile::parse_secs,
+    multi_tasks::{
+        multi_tasks_impl, print_confirm_stat, print_warmup_stat, task_confirm_ms, Warmup,
+    },
+    networks::Network,
+    preflight::{check_spend, PlannedTx},
+    rate_limit::Limiter,
+    retry::{Retry, RetryPolicy},
+    signer::resolve_signer,
+    task_impl::{
+        contract_call, contract_deploy, contract_query, transfer, wait_receipt, TxParams, CALL_GAS,
+        DEPLOY_GAS, TRANSFER_GAS,
+    },
+};
+use anyhow::{anyhow, bail};
+use futures::{future::BoxFuture, FutureExt};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use web3::types::{AccessList, TransactionReceipt, H160, U256};
+
+// outputs of the steps run so far by step name, like "token" -> "address" -> "0x.."
+type Outputs = HashMap<String, HashMap<String, String>>;
+// the outputs of the first task of a step or a mix operation to succeed
+type FirstOutput = Arc<Mutex<Option<HashMap<String, String>>>>;
+// a task of any kind, so one load phase can mix deploys, calls, queries and transfers
+type Task = Box<dyn FnOnce(Lease) -> BoxFuture<'static, anyhow::Result<()>>>;
+// the tasks with their senders and the transactions they plan, for the spend check
+type Tasks = (Vec<(Option<H160>, Task)>, Vec<PlannedTx>);
+
+// scenario.yaml:
+//   steps:
+//     - name: token
+//       deploy: {code_path: test.bin, abi_path: test.abi, sec_key: "key:deployer", args: ""}
+//     - name: store
+//       count: 100
+//       call: {contract_addr: "${steps.token.address}", abi_path: test.abi, ...}
+//     - name: stored
+//       query: {contract_addr: "${steps.token.address}", abi_path: test.abi, ...}
+//     - assert: {value: "${steps.stored.result}", equals: "12345"}
+//     - name: traffic
+//       count: 1000
+//       warmup: 30s
+//       mix:
+//         - {weight: 70, call: {func_name: store, ...}}
+//         - {weight: 20, query: {func_name: retrieve, ...}}
+//         - {weight: 10, deploy: {...}}
+#[derive(Deserialize)]
+struct ScenarioFile {
+    // kept as yaml until the step runs, its references need the earlier outputs
+    steps: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct Step {
+    #[serde(default)]
+    name: Option<String>,
+    // tasks started at once, above 1 the step is a load phase
+    #[serde(default = "one")]
+    count: u32,
+    // the first part of a load phase, like "30s", whose tasks run but stay out of the stats
+    #[serde(default)]
+    warmup: Option<String>,
+    // the first tasks of a load phase, which run but stay out of the stats
+    #[serde(default)]
+    warmup_count: Option<u32>,
+    #[serde(flatten)]
+    action: Action,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Deploy(DeployJsonObj),
+    Call(CallJsonObj),
+    Query(QueryJson),
+    Transfer(TransferJsonObj),
+    Assert(Assert),
+    Mix(Vec<MixEntry>),
+}
+
+// one operation of a mix, picked for `weight` out of the total weight of the tasks
+#[derive(Deserialize)]
+struct MixEntry {
+    // the operation in the stats and outputs, the function name of calls and queries if not set
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default = "one")]
+    weight: u32,
+    #[serde(flatten)]
+    action: Action,
+}
+
+// stats of one operation of a mix
+struct Op {
+    name: String,
+    first: FirstOutput,
+    tasks: AtomicU32,
+    success: AtomicU32,
+    total_ms: AtomicU64,
+}
+
+// numbers compare by value, so "1FRA" equals "1000000000000000000"
+#[derive(Deserialize)]
+struct Assert {
+    value: String,
+    equals: String,
+}
+
+// what the steps of a scenario share, set up once from the command line
+pub(crate) struct Runner {
+    pub(crate) endpoints: Arc<Endpoints>,
+    pub(crate) limiter: Arc<Limiter>,
+    pub(crate) network: Network,
+    pub(crate) chain_id: u64,
+    pub(crate) tx_type: TxType,
+    pub(crate) gas_multiplier: f64,
+    pub(crate) retry: RetryPolicy,
+    pub(crate) max_spend: Option<U256>,
+    // worst-case spend of the steps so far
+    pub(crate) planned_spend: tokio::sync::Mutex<U256>,
+    pub(crate) address_book: tokio::sync::Mutex<AddressBook>,
+    // deploys of the running step, recorded in the address book when it is done
+    pub(crate) deployed: Deployed,
+}
+
+impl Runner {
+    // run the steps in order, stopping at the first one where no task succeeds
+    pub(crate) async fn run(&self, path: &Path) -> anyhow::Result<()> {
+        let content = tokio::fs::read(path).await?;
+        let scenario: ScenarioFile = serde_yaml::from_slice(&content)
+            .map_err(|e| anyhow!("parse scenario {:?} failed: {}", path, e))?;
+
+        let mut outputs = Outputs::new();
+        for (index, mut step) in scenario.steps.into_iter().enumerate() {
+            substitute(&mut step, &outputs)?;
+            let step: Step = serde_yaml::from_value(step)
+                .map_err(|e| anyhow!("step {} of {:?}: {}", index + 1, path, e))?;
+            let name = step.name.clone().unwrap_or_else(|| (index + 1).to_string());
+            println!("step {}", name);
+
+            let count = step.count.max(1);
+            let warmup = Warmup {
+                secs: step.warmup.as_deref().map(parse_secs).transpose()?,
+                count: step.warmup_count,
+            };
+            let output = match step.action {
+                Action::Assert(assert) => {
+                    check_assert(&assert)?;
+                    println!("assert passed: {} equals {}", assert.value, assert.equals);
+                    HashMap::new()
+                }
+                Action::Mix(entries) => self.mix(entries, count, warmup).await?,
+                action => {
+                    let first = FirstOutput::default();
+                    let (vf, planned) = self
+                        .tasks(step.name.as_deref(), action, count, &first)
+                        .await?;
+                    let success_task = self.run_tasks(vf, &planned, warmup).await?;
+                    let mut output = match first.lock().unwrap().take() {
+                        Some(output) => output,
+                        None => bail!("no task of step {} succeeded", name),
+                    };
+                    output.insert("success".to_string(), success_task.to_string());
+                    output
+                }
+            };
+            outputs.insert(name, output);
+        }
+        println!("scenario done: {} steps", outputs.len());
+
+        Ok(())
+    }
+
+    fn tx_params(
+        &self,
+        gas: Option<GasValue>,
+        gas_price: Option<GasValue>,
+        max_fee_per_gas: Option<GasValue>,
+        max_priority_fee_per_gas: Option<GasValue>,
+        access_list: Option<AccessList>,
+        value: Option<U256>,
+    ) -> TxParams {
+        self.network.gas_defaults(TxParams {
+            gas,
+            gas_price,
+            gas_multiplier: self.gas_multiplier,
+            value,
+            tx_type: self.tx_type,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            access_list,
+            chain_id: Some(self.chain_id),
+        })
+    }
+
+    // `count` tasks of a deploy, call, query or transfer for each of its senders
+    async fn tasks(
+        &self,
+        step_name: Option<&str>,
+        action: Action,
+        count: u32,
+        first: &FirstOutput,
+    ) -> anyhow::Result<Tasks> {
+        match action {
+            Action::Deploy(deploy_obj) => self.deploy_tasks(step_name, deploy_obj, count, first),
+            Action::Call(call_obj) => self.call_tasks(call_obj, count, first).await,
+            Action::Query(query) => self.query_tasks(query, count, first).await,
+            Action::Transfer(transfer_obj) => self.transfer_tasks(transfer_obj, count, first),
+            Action::Assert(_) | Action::Mix(_) => bail!("a mix can't hold assert or mix steps"),
+        }
+    }
+
+    fn deploy_tasks(
+        &self,
+        step_name: Option<&str>,
+        deploy_obj: DeployJsonObj,
+        count: u32,
+        first: &FirstOutput,
+    ) -> anyhow::Result<Tasks> {
+        // the address book name is the one of the deploy, else the step name or the abi file name
+        let book_name = match (&deploy_obj.name, step_name) {
+            (Some(name), _) => name.clone(),
+            (None, Some(name)) => name.to_string(),
+            (None, None) => file_stem(&deploy_obj.abi_path),
+        };
+        let args = parse_args_csv(&deploy_obj.args)?;
+        let value = deploy_obj.value.as_deref().map(parse_amount).transpose()?;
+        let tx_params = self.tx_params(
+            deploy_obj.gas,
+            deploy_obj.gas_price,
+            deploy_obj.max_fee_per_gas,
+            deploy_obj.max_priority_fee_per_gas,
+            deploy_obj.access_list,
+            value,
+        );
+
+        let mut vf = Vec::new();
+        let mut planned = Vec::new();
+        for sec_key in resolve_keys(
+            &self.network,
+            &deploy_obj.sec_key,
+            deploy_obj.senders.as_deref(),
+        )? {
+            let signer = resolve_signer(&self.network, &sec_key)?;
+            for _ in 0..count {
+                planned.push(PlannedTx {
+                    from: signer.address(),
+                    default_gas: DEPLOY_GAS,
+                    tx_params: tx_params.clone(),
+                });
+
+                let deployed = self.deployed.clone();
+                let first = first.clone();
+                let signer = signer.clone();
+                let code_path = deploy_obj.code_path.clone();
+                let abi_path = deploy_obj.abi_path.clone();
+                let book_abi_path = abi_path.clone();
+                let book_name = book_name.clone();
+                let tx_params = tx_params.clone();
+                let args = args.clone();
+                let retry_policy = self.retry;
+                let sender = signer.address();
+                let f: Task = Box::new(move |lease| {
+                    async move {
+                        let retry = &Retry::new(retry_policy, lease.warming());
+                        let res = lease
+                            .call(|rpc_url| async move {
+                                contract_deploy(
+                                    &rpc_url,
+                                    signer.as_ref(),
+                                    &code_path,
+                                    &abi_path,
+                                    tx_params,
+                                    args,
+                                    retry,
+                                )
+                                .await
+                            })
+                            .await;
+                        match res {
+                            Ok((address, receipt)) => {
+                                println!("contract address: {:?}{}", address, retry.note());
+                                deployed.lock().unwrap().push((
+                                    book_name,
+                                    Deployment {
+                                        address,
+                                        abi_path: book_abi_path,
+                                        transaction_hash: receipt.transaction_hash,
+                                        block_number: receipt.block_number.map(|n| n.as_u64()),
+                                        deployer: sender,
+                                    },
+                                ));
+                                let mut output = HashMap::new();
+                                output.insert("address".to_string(), format!("{:?}", address));
+                                output.insert(
+                                    "transaction_hash".to_string(),
+                                    format!("{:?}", receipt.transaction_hash),
+                                );
+                                if let Some(block_number) = receipt.block_number {
+                                    output.insert(
+                                        "block_number".to_string(),
+                                        block_number.to_string(),
+                                    );
+                                }
+                                first.lock().unwrap().get_or_insert(output);
+                                Ok(())
+                            }
+                            Err(e) => {
+                                println!("deploy contract failed{}: {:?}", retry.note(), e);
+                                bail!("deploy failed");
+                            }
+                        }
+                    }
+                    .boxed()
+                });
+
+                vf.push((Some(sender), f));
+            }
+        }
+
+        Ok((vf, planned))
+    }
+
+    async fn call_tasks(
+        &self,
+        call_obj: CallJsonObj,
+        count: u32,
+        first: &FirstOutput,
+    ) -> anyhow::Result<Tasks> {
+        let contract_addr = self
+            .address_book
+            .lock()
+            .await
+            .resolve(&call_obj.contract_addr)?;
+        let args = parse_args_csv(&call_obj.args)?;
+        let value = call_obj.value.as_deref().map(parse_amount).transpose()?;
+        let tx_params = self.tx_params(
+            call_obj.gas,
+            call_obj.gas_price,
+            call_obj.max_fee_per_gas,
+            call_obj.max_priority_fee_per_gas,
+            call_obj.access_list,
+            value,
+        );
+
+        let mut vf = Vec::new();
+        let mut planned = Vec::new();
+        for sec_key in resolve_keys(
+            &self.network,
+            &call_obj.sec_key,
+            call_obj.senders.as_deref(),
+        )? {
+            let signer = resolve_signer(&self.network, &sec_key)?;
+            for _ in 0..count {
+                planned.push(PlannedTx {
+                    from: signer.address(),
+                    default_gas: CALL_GAS,
+                    tx_params: tx_params.clone(),
+                });
+
+                let first = first.clone();
+                let signer = signer.clone();
+                let contract_addr = contract_addr.clone();
+                let abi_path = call_obj.abi_path.clone();
+                let func_name = call_obj.func_name.clone();
+                let tx_params = tx_params.clone();
+                let args = args.clone();
+                let retry_policy = self.retry;
+                let sender = signer.address();
+                let f: Task = Box::new(move |lease| {
+                    async move {
+                        let retry = &Retry::new(retry_policy, lease.warming());
+                        // later steps see the state of the call, so its receipt is waited for
+                        let res = lease
+                            .call(|rpc_url| async move {
+                                let v = contract_call(
+                                    &rpc_url,
+                                    signer.as_ref(),
+                                    &contract_addr,
+                                    &abi_path,
+                                    tx_params,
+                                    &func_name,
+                                    args,
+                                    retry,
+                                )
+                                .await?;
+                                println!("transaction hash: {:?}{}", v, retry.note());
+                                Ok::<_, anyhow::Error>(
+                                    retry
+                                        .run(|| wait_receipt(&rpc_url, v, retry.receipt_timeout()))
+                                        .await?,
+                                )
+                            })
+                            .await;
+                        match res {
+                            Ok(receipt) if receipt.status == Some(0.into()) => {
+                                println!("transaction reverted: {:?}", receipt.transaction_hash);
+                                bail!("call reverted");
+                            }
+                            Ok(receipt) => {
+                                first
+                                    .lock()
+                                    .unwrap()
+                                    .get_or_insert(receipt_output(&receipt));
+                                Ok(())
+                            }
+                            Err(e) => {
+                                println!("call contract failed{}: {:?}", retry.note(), e);
+                                bail!("call failed");
+                            }
+                        }
+                    }
+                    .boxed()
+                });
+
+                vf.push((Some(sender), f));
+            }
+        }
+
+        Ok((vf, planned))
+    }
+
+    async fn query_tasks(
+        &self,
+        query: QueryJson,
+        count: u32,
+        first: &FirstOutput,
+    ) -> anyhow::Result<Tasks> {
+        let contract_addr = self
+            .address_book
+            .lock()
+            .await
+            .resolve(&query.contract_addr)?;
+        let args = parse_args_csv(&query.args)?;
+
+        let mut vf = Vec::new();
+        for _ in 0..count {
+            let first = first.clone();
+            let contract_addr = contract_addr.clone();
+            let abi_path = query.abi_path.clone();
+            let func_name = query.func_name.clone();
+            let args = args.clone();
+            let f: Task = Box::new(move |lease| {
+                async move {
+                    let res = lease
+                        .call(|rpc_url| async move {
+                            contract_query(&rpc_url, &contract_addr, &abi_path, &func_name, args)
+                                .await
+                        })
+                        .await;
+                    match res {
+                        Ok(result) => {
+                            println!("query result: {:?}", result);
+                            let mut output = HashMap::new();
+                            output.insert("result".to_string(), result.to_string());
+                            first.lock().unwrap().get_or_insert(output);
+                            Ok(())
+                        }
+                        Err(e) => {
+                            println!("query contract failed: {:?}", e);
+                            bail!("query failed");
+                        }
+                    }
+                }
+                .boxed()
+            });
+
+            // queries don't send anything, no sender limit holds them back
+            vf.push((None, f));
+        }
+
+        Ok((vf, Vec::new()))
+    }
+
+    fn transfer_tasks(
+        &self,
+        transfer_obj: TransferJsonObj,
+        count: u32,
+        first: &FirstOutput,
+    ) -> anyhow::Result<Tasks> {
+        let tx_params = self.tx_params(
+            transfer_obj.gas,
+            transfer_obj.gas_price,
+            transfer_obj.max_fee_per_gas,
+            transfer_obj.max_priority_fee_per_gas,
+            transfer_obj.access_list,
+            Some(parse_amount(&transfer_obj.amount)?),
+        );
+
+        let mut vf = Vec::new();
+        let mut planned = Vec::new();
+        for sec_key in resolve_keys(
+            &self.network,
+            &transfer_obj.sec_key,
+            transfer_obj.senders.as_deref(),
+        )? {
+            let signer = resolve_signer(&self.network, &sec_key)?;
+            for _ in 0..count {
+                planned.push(PlannedTx {
+                    from: signer.address(),
+                    default_gas: TRANSFER_GAS,
+                    tx_params: tx_params.clone(),
+                });
+
+                let first = first.clone();
+                let signer = signer.clone();
+                let to = transfer_obj.to.clone();
+                let tx_params = tx_params.clone();
+                let retry_policy = self.retry;
+                let sender = signer.address();
+                let f: Task = Box::new(move |lease| {
+                    async move {
+                        let retry = &Retry::new(retry_policy, lease.warming());
+                        let res = lease
+                            .call(|rpc_url| async move {
+                                let v = transfer(&rpc_url, signer.as_ref(), &to, tx_params, retry)
+                                    .await?;
+                                println!("transaction hash: {:?}{}", v, retry.note());
+                                Ok::<_, anyhow::Error>(
+                                    retry
+                                        .run(|| wait_receipt(&rpc_url, v, retry.receipt_timeout()))
+                                        .await?,
+                                )
+                            })
+                            .await;
+                        match res {
+                            Ok(receipt) => {
+                                first
+                                    .lock()
+                                    .unwrap()
+                                    .get_or_insert(receipt_output(&receipt));
+                                Ok(())
+                            }
+                            Err(e) => {
+                                println!("transfer failed{}: {:?}", retry.note(), e);
+                                bail!("transfer failed");
+                            }
+                        }
+                    }
+                    .boxed()
+                });
+
+                vf.push((Some(sender), f));
+            }
+        }
+
+        Ok((vf, planned))
+    }
+
+    // `count` tasks spread over the entries by weight, interleaved in one load phase
+    async fn mix(
+        &self,
+        entries: Vec<MixEntry>,
+        count: u32,
+        warmup: Warmup,
+    ) -> anyhow::Result<HashMap<String, String>> {
+        let weights: Vec<u32> = entries.iter().map(|entry| entry.weight).collect();
+        if weights.iter().all(|weight| *weight == 0) {
+            bail!("a mix needs an entry with a weight above 0");
+        }
+        let picks = weighted_order(&weights, count as usize);
+
+        let mut ops: Vec<Arc<Op>> = Vec::new();
+        let mut queues = Vec::new();
+        let mut planned = Vec::new();
+        for (index, entry) in entries.into_iter().enumerate() {
+            let name = match (entry.name, &entry.action) {
+                (Some(name), _) => name,
+                (None, Action::Call(call_obj)) => call_obj.func_name.clone(),
+                (None, Action::Query(query)) => query.func_name.clone(),
+                (None, Action::Deploy(_)) => "deploy".to_string(),
+                (None, _) => "transfer".to_string(),
+            };
+            if ops.iter().any(|op| op.name == name) {
+                bail!(
+                    "two mix entries are named {}, set a name on one of them",
+                    name
+                );
+            }
+
+            let op = Arc::new(Op {
+                name,
+                first: FirstOutput::default(),
+                tasks: AtomicU32::new(0),
+                success: AtomicU32::new(0),
+                total_ms: AtomicU64::new(0),
+            });
+            let count = picks.iter().filter(|pick| **pick == index).count() as u32;
+            let (vf, entry_planned) = self.tasks(None, entry.action, count, &op.first).await?;
+            let queue: VecDeque<(Option<H160>, Task)> = vf
+                .into_iter()
+                .map(|(sender, f)| {
+                    let op = op.clone();
+                    let f: Task = Box::new(move |lease| {
+                        let warming = lease.warming();
+                        let af = f(lease);
+                        async move {
+                            let beg = Instant::now();
+                            let res = af.await;
+                            if warming {
+                                return res;
+                            }
+                            op.tasks.fetch_add(1, Ordering::AcqRel);
+                            if res.is_ok() {
+                                // like the task time, without the wait for the receipt
+                                let millis = (beg.elapsed().as_millis() as u64)
+                                    .saturating_sub(task_confirm_ms());
+                                op.success.fetch_add(1, Ordering::AcqRel);
+                                op.total_ms.fetch_add(millis, Ordering::AcqRel);
+                            }
+                            res
+                        }
+                        .boxed()
+                    });
+                    (sender, f)
+                })
+                .collect();
+            queues.push(queue);
+            planned.extend(entry_planned);
+            ops.push(op);
+        }
+
+        // entries with several senders have more tasks than picks, so interleave what was built
+        let lengths: Vec<u32> = queues.iter().map(|queue| queue.len() as u32).collect();
+        let total = lengths.iter().sum::<u32>() as usize;
+        let mut vf = Vec::with_capacity(total);
+        for index in weighted_order(&lengths, total) {
+            vf.push(queues[index].pop_front().unwrap());
+        }
+        let success_task = self.run_tasks(vf, &planned, warmup).await?;
+
+        println!(
+            "{:<20} {:>10} {:>10} {:>16}",
+            "operation", "tasks", "success", "avg time ms"
+        );
+        let mut output = HashMap::new();
+        for op in &ops {
+            let success = op.success.load(Ordering::Acquire);
+            let total_ms = op.total_ms.load(Ordering::Acquire);
+            println!(
+                "{:<20} {:>10} {:>10} {:>16}",
+                op.name,
+                op.tasks.load(Ordering::Acquire),
+                success,
+                if success == 0 {
+                    0
+                } else {
+                    total_ms / success as u64
+                }
+            );
+
+            // "<operation>.<output>" of the first task of each operation to succeed
+            if let Some(first) = op.first.lock().unwrap().take() {
+                for (key, value) in first {
+                    output.insert(format!("{}.{}", op.name, key), value);
+                }
+            }
+            output.insert(format!("{}.success", op.name), success.to_string());
+        }
+        if success_task == 0 {
+            bail!("no task of the mix succeeded");
+        }
+        output.insert("success".to_string(), success_task.to_string());
+
+        Ok(output)
+    }
+
+    // one load phase, returns the tasks which succeeded
+    async fn run_tasks(
+        &self,
+        vf: Vec<(Option<H160>, Task)>,
+        planned: &[PlannedTx],
+        warmup: Warmup,
+    ) -> anyhow::Result<u32> {
+        // the max spend is for the whole run, the worst case of earlier steps counts toward it
+        if !planned.is_empty() {
+            let earlier = *self.planned_spend.lock().await;
+            let spend =
+                check_spend(self.endpoints.primary(), planned, self.max_spend, earlier).await?;
+            *self.planned_spend.lock().await = earlier.saturating_add(spend);
+        }
+
+        let total_task = vf.len();
+        let (success_task, total_times) =
+            multi_tasks_impl(vf, self.endpoints.clone(), self.limiter.clone(), warmup).await?;
+        // later steps resolve the contracts of this one by name
+        self.address_book.lock().await.record(&self.deployed).await;
+        if total_task > 1 {
+            println!(
+                "success task: {} of {} total times: {} average time: {}",
+                success_task,
+                total_task,
+                total_times,
+                if success_task == 0 {
+                    0
+                } else {
+                    total_times / success_task as u128
+                }
+            );
+            print_confirm_stat();
+            print_warmup_stat();
+        }
+        self.limiter.print_phase_stats();
+
+        Ok(success_task)
+    }
+}
+
+// smooth weighted round-robin like the weighted endpoints, over the total weight each index
+// comes up `weight` times, spread out
+fn weighted_order(weights: &[u32], n: usize) -> Vec<usize> {
+    let total: i64 = weights.iter().map(|weight| *weight as i64).sum();
+    let mut current = vec![0i64; weights.len()];
+    let mut order = Vec::with_capacity(n);
+    for _ in 0..n {
+        for (current, weight) in current.iter_mut().zip(weights) {
+            *current += *weight as i64;
+        }
+        let picked = (0..weights.len()).max_by_key(|i| current[*i]).unwrap();
+        current[picked] -= total;
+        order.push(picked);
+    }
+
+    order
+}
+
+fn receipt_output(receipt: &TransactionReceipt) -> HashMap<String, String> {
+    let mut output = HashMap::new();
+    output.insert(
+        "transaction_hash".to_string(),
+        format!("{:?}", receipt.transaction_hash),
+    );
+    if let Some(block_number) = receipt.block_number {
+        output.insert("block_number".to_string(), block_number.to_string());
+    }
+    output
+}
+
+fn check_assert(assert: &Assert) -> anyhow::Result<()> {
+    let equal = match (parse_amount(&assert.value), parse_amount(&assert.equals)) {
+        (Ok(value), Ok(equals)) => value == equals,
+        _ => assert.value.eq_ignore_ascii_case(&assert.equals),
+    };
+    if !equal {
+        bail!("assert failed: {} is not {}", assert.value, assert.equals);
+    }
+
+    Ok(())
+}
+
+// replace the ${steps.<name>.<output>} references in every string of a step
+fn substitute(value: &mut Value, outputs: &Outputs) -> anyhow::Result<()> {
+    match value {
+        Value::String(s) => *s = expand(s, outputs)?,
+        Value::Sequence(seq) => {
+            for v in seq {
+                substitute(v, outputs)?;
+            }
+        }
+        Value::Mapping(map) => {
+            for (_, v) in map.iter_mut() {
+                substitute(v, outputs)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+fn expand(s: &str, outputs: &Outputs) -> anyhow::Result<String> {
+    let mut res = String::new();
+    let mut rest = s;
+    while let Some(beg) = rest.find("${") {
+        res.push_str(&rest[..beg]);
+        let end = match rest[beg..].find('}') {
+            Some(end) => beg + end,
+            None => bail!("unclosed reference in {:?}", s),
+        };
+        let reference = &rest[beg + 2..end];
+        let output = reference
+            .strip_prefix("steps.")
+            .and_then(|path| path.split_once('.'))
+            .and_then(|(step, key)| outputs.get(step).and_then(|output| output.get(key)));
+        match output {
+            Some(output) => res.push_str(output),
+            None => bail!(
+                "unknown reference ${{{}}}, no such earlier step output",
+                reference
+            ),
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs() -> Outputs {
+        let mut token = HashMap::new();
+        token.insert("address".to_string(), "0xabc".to_string());
+        let mut outputs = Outputs::new();
+        outputs.insert("token".to_string(), token);
+        outputs
+    }
+
+    #[test]
+    fn weighted() {
+        assert_eq!(weighted_order(&[3, 1], 4), [0, 1, 0, 0]);
+        assert_eq!(weighted_order(&[1, 0], 3), [0, 0, 0]);
+        let order = weighted_order(&[70, 20, 10], 100);
+        for (index, weight) in [70, 20, 10].iter().enumerate() {
+            assert_eq!(order.iter().filter(|i| **i == index).count(), *weight);
+        }
+    }
+
+    #[test]
+    fn references() {
+        let outputs = outputs();
+        assert_eq!(expand("${steps.token.address}", &outputs).unwrap(), "0xabc");
+        assert_eq!(
+            expand("a ${steps.token.address} b $x", &outputs).unwrap(),
+            "a 0xabc b $x"
+        );
+        assert!(expand("${steps.token.owner}", &outputs).is_err());
+        assert!(expand("${steps.coin.address}", &outputs).is_err());
+        assert!(expand("${steps.token.address", &outputs).is_err());
+    }
+
+    #[test]
+    fn substitute_step() {
+        let mut step: Value = serde_yaml::from_str(
+            "call: {contract_addr: \"${steps.token.address}\", args: [\"${steps.token.address}\", 1]}",
+        )
+        .unwrap();
+        substitute(&mut step, &outputs()).unwrap();
+        assert_eq!(
+            step,
+            serde_yaml::from_str::<Value>("call: {contract_addr: \"0xabc\", args: [\"0xabc\", 1]}")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn asserts() {
+        let assert = |value: &str, equals: &str| Assert {
+            value: value.to_string(),
+            equals: equals.to_string(),
+        };
+        assert!(check_assert(&assert("1FRA", "1000000000000000000")).is_ok());
+        assert!(check_assert(&assert("0xABC", "0xabc")).is_ok());
+        assert!(check_assert(&assert("7", "8")).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{anyhow, bail};
+use secp256k1::{
+    ecdsa::{RecoverableSignature, RecoveryId},
+    Message, Secp256k1, SecretKey,
+};
+use serde_json::{Map, Value};
+use std::{collections::BTreeSet, str::FromStr};
+use web3::{
+    signing::keccak256,
+    types::{H160, U256},
+};
+
+// message given as 0x prefixed hex is signed as raw bytes, otherwise as utf8 text
+pub(crate) fn message_bytes(message: &str) -> Vec<u8> {
+    if let Some(hex_str) = message.strip_prefix("0x") {
+        if let Ok(bytes) = hex::decode(hex_str) {
+            return bytes;
+        }
+    }
+
+    message.as_bytes().to_vec()
+}
+
+// EIP-191 personal_sign hash
+pub(crate) fn personal_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    data.extend_from_slice(message);
+
+    keccak256(&data)
+}
+
+// EIP-712 hash of a typed data json with types, primaryType, domain and message
+pub(crate) fn typed_data_hash(typed_data: &Value) -> anyhow::Result<[u8; 32]> {
+    let types = match typed_data["types"].as_object() {
+        Some(types) => types,
+        None => bail!("typed data has no types"),
+    };
+    let primary_type = match typed_data["primaryType"].as_str() {
+        Some(primary_type) => primary_type,
+        None => bail!("typed data has no primaryType"),
+    };
+
+    let mut data = vec![0x19, 0x01];
+    data.extend_from_slice(&hash_struct(types, "EIP712Domain", &typed_data["domain"])?);
+    if primary_type != "EIP712Domain" {
+        data.extend_from_slice(&hash_struct(types, primary_type, &typed_data["message"])?);
+    }
+
+    Ok(keccak256(&data))
+}
+
+// 65 bytes signature r || s || v with v = 27 or 28
+pub(crate) fn sign_hash(sec_key: &str, hash: &[u8; 32]) -> anyhow::Result<Vec<u8>> {
+    let secretkey = SecretKey::from_str(sec_key)?;
+    let message = Message::from_slice(hash)?;
+
+    let signature = Secp256k1::signing_only().sign_ecdsa_recoverable(&message, &secretkey);
+    let (recovery_id, rs) = signature.serialize_compact();
+
+    let mut res = rs.to_vec();
+    res.push(27 + recovery_id.to_i32() as u8);
+
+    Ok(res)
+}
+
+// address of the key that made the 65 bytes signature, v can be 0, 1, 27 or 28
+pub(crate) fn recover(hash: &[u8; 32], signature: &[u8]) -> anyhow::Result<H160> {
+    if signature.len() != 65 {
+        bail!("signature should be 65 bytes, got {}", signature.len());
+    }
+    let v = match signature[64] {
+        v @ 0..=1 => v,
+        v @ 27..=28 => v - 27,
+        v => bail!("invalid signature v {}", v),
+    };
+
+    let recovery_id = RecoveryId::from_i32(v as i32)?;
+    let signature = RecoverableSignature::from_compact(&signature[..64], recovery_id)?;
+    let message = Message::from_slice(hash)?;
+    let public_key = Secp256k1::verification_only().recover_ecdsa(&message, &signature)?;
+
+    let hash = keccak256(&public_key.serialize_uncompressed()[1..]);
+    Ok(H160::from_slice(&hash[12..]))
+}
+
+type Types = Map<String, Value>;
+
+fn fields<'a>(types: &'a Types, name: &str) -> anyhow::Result<Vec<(&'a str, &'a str)>> {
+    let fields = match types.get(name).and_then(|v| v.as_array()) {
+        Some(fields) => fields,
+        None => bail!("typed data has no type {}", name),
+    };
+
+    let mut res = Vec::with_capacity(fields.len());
+    for field in fields {
+        match (field["name"].as_str(), field["type"].as_str()) {
+            (Some(field_name), Some(field_type)) => res.push((field_name, field_type)),
+            _ => bail!("type {} has a field without name or type", name),
+        }
+    }
+
+    Ok(res)
+}
+
+fn base_type(ty: &str) -> &str {
+    match ty.find('[') {
+        Some(i) => &ty[..i],
+        None => ty,
+    }
+}
+
+fn find_deps(types: &Types, ty: &str, found: &mut BTreeSet<String>) -> anyhow::Result<()> {
+    let ty = base_type(ty);
+    if found.contains(ty) || !types.contains_key(ty) {
+        return Ok(());
+    }
+
+    found.insert(ty.to_string());
+    for (_, field_type) in fields(types, ty)? {
+        find_deps(types, field_type, found)?;
+    }
+
+    Ok(())
+}
+
+fn encode_type(types: &Types, primary_type: &str) -> anyhow::Result<String> {
+    let mut deps = BTreeSet::new();
+    find_deps(types, primary_type, &mut deps)?;
+    deps.remove(primary_type);
+
+    let mut res = String::new();
+    for ty in std::iter::once(primary_type).chain(deps.iter().map(|d| d.as_str())) {
+        let fields: Vec<String> = fields(types, ty)?
+            .iter()
+            .map(|(field_name, field_type)| format!("{} {}", field_type, field_name))
+            .collect();
+        res.push_str(&format!("{}({})", ty, fields.join(",")));
+    }
+
+    Ok(res)
+}
+
+fn hash_struct(types: &Types, ty: &str, value: &Value) -> anyhow::Result<[u8; 32]> {
+    let mut data = keccak256(encode_type(types, ty)?.as_bytes()).to_vec();
+    for (field_name, field_type) in fields(types, ty)? {
+        data.extend_from_slice(&encode_value(types, field_type, &value[field_name])?);
+    }
+
+    Ok(keccak256(&data))
+}
+
+fn encode_value(types: &Types, ty: &str, value: &Value) -> anyhow::Result<[u8; 32]> {
+    if let Some((item_type, _)) = ty.strip_suffix(']').and_then(|t| t.rsplit_once('[')) {
+        let items = match value.as_array() {
+            Some(items) => items,
+            None => bail!("{} value should be an array", ty),
+        };
+        let mut data = Vec::with_capacity(items.len() * 32);
+        for item in items {
+            data.extend_from_slice(&encode_value(types, item_type, item)?);
+        }
+        return Ok(keccak256(&data));
+    }
+    if types.contains_key(ty) {
+        return hash_struct(types, ty, value);
+    }
+
+    let mut res = [0u8; 32];
+    match ty {
+        "string" => res = keccak256(value_str(ty, value)?.as_bytes()),
+        "bytes" => res = keccak256(&value_hex(ty, value)?),
+        "bool" => {
+            if value_bool(value)? {
+                res[31] = 1;
+            }
+        }
+        "address" => {
+            let address: H160 = value_str(ty, value)?.parse()?;
+            res[12..].copy_from_slice(address.as_bytes());
+        }
+        _ if ty.starts_with("bytes") => {
+            let bytes = value_hex(ty, value)?;
+            if bytes.len() > 32 {
+                bail!("{} value is too long", ty);
+            }
+            res[..bytes.len()].copy_from_slice(&bytes);
+        }
+        _ if ty.starts_with("uint") => {
+            let (negative, n) = value_int(value)?;
+            if negative {
+                bail!("{} value is negative", ty);
+            }
+            n.to_big_endian(&mut res);
+        }
+        _ if ty.starts_with("int") => {
+            let (negative, n) = value_int(value)?;
+            let n = if negative {
+                (!n).overflowing_add(U256::one()).0
+            } else {
+                n
+            };
+            n.to_big_endian(&mut res);
+        }
+        _ => bail!("unknown typed data type {}", ty),
+    }
+
+    Ok(res)
+}
+
+fn value_str<'a>(ty: &str, value: &'a Value) -> anyhow::Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| anyhow!("{} value should be a string", ty))
+}
+
+fn value_hex(ty: &str, value: &Value) -> anyhow::Result<Vec<u8>> {
+    let s = value_str(ty, value)?;
+    Ok(hex::decode(s.strip_prefix("0x").unwrap_or(s))?)
+}
+
+fn value_bool(value: &Value) -> anyhow::Result<bool> {
+    match value {
+        Value::Bool(b) => Ok(*b),
+        Value::String(s) => Ok(s.parse()?),
+        _ => bail!("bool value should be true or false"),
+    }
+}
+
+// integer given as json number, decimal string or 0x prefixed hex string
+fn value_int(value: &Value) -> anyhow::Result<(bool, U256)> {
+    let s = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.trim().to_string(),
+        _ => bail!("integer value should be a number or a string"),
+    };
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.as_str()),
+    };
+
+    let n = match digits.strip_prefix("0x") {
+        Some(hex_digits) => U256::from_str_radix(hex_digits, 16)?,
+        None => U256::from_dec_str(digits).map_err(|e| anyhow!("integer format error: {:?}", e))?,
+    };
+
+    Ok((negative, n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // the example of EIP-712, signed with keccak256("cow")
+    fn mail() -> Value {
+        json!({
+            "types": {
+                "EIP712Domain": [
+                    {"name": "name", "type": "string"},
+                    {"name": "version", "type": "string"},
+                    {"name": "chainId", "type": "uint256"},
+                    {"name": "verifyingContract", "type": "address"}
+                ],
+                "Person": [
+                    {"name": "name", "type": "string"},
+                    {"name": "wallet", "type": "address"}
+                ],
+                "Mail": [
+                    {"name": "from", "type": "Person"},
+                    {"name": "to", "type": "Person"},
+                    {"name": "contents", "type": "string"}
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+                "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+                "contents": "Hello, Bob!"
+            }
+        })
+    }
+
+    #[test]
+    fn typed_data() {
+        let hash = typed_data_hash(&mail()).unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        let cow = hex::encode(keccak256(b"cow"));
+        let signature = sign_hash(&cow, &hash).unwrap();
+        assert_eq!(
+            hex::encode(&signature),
+            "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+             07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562\
+             1c"
+        );
+        assert_eq!(
+            recover(&hash, &signature).unwrap(),
+            "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"
+                .parse()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn bad_typed_data() {
+        let mut typed_data = mail();
+        typed_data["primaryType"] = json!("Letter");
+        assert!(typed_data_hash(&typed_data).is_err());
+        assert!(typed_data_hash(&json!({"primaryType": "Mail"})).is_err());
+    }
+}
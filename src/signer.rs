@@ -0,0 +1,246 @@
+use crate::{
+    keys::resolve_key,
+    networks::Network,
+    transport::{http, AnyTransport},
+};
+use anyhow::{anyhow, bail};
+use async_trait::async_trait;
+use lazy_static::lazy_static;
+use secp256k1::SecretKey;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    future::Future,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use web3::{
+    helpers::CallFuture,
+    signing::Key,
+    transports::Http,
+    types::{BlockNumber, Bytes, TransactionParameters, H160, U256, U64},
+    Transport, Web3,
+};
+
+// signs the transactions of one sender, the key lives in this process or in a signer service
+#[async_trait]
+pub(crate) trait TxSigner: Send + Sync {
+    fn address(&self) -> H160;
+
+    // raw signed transaction, nonce, fees and chain id which are not set are taken from the node
+    async fn sign_transaction(
+        &self,
+        web3: &Web3<AnyTransport>,
+        tx: TransactionParameters,
+    ) -> web3::Result<Bytes>;
+}
+
+// a `sec_key` value is a remote signer as `remote:<url>#<address>`, otherwise a key for `resolve_key`
+pub(crate) fn resolve_signer(
+    network: &Network,
+    key_ref: &str,
+) -> anyhow::Result<Arc<dyn TxSigner>> {
+    let key_ref = &network.expand_key_ref(key_ref)?;
+    if let Some(remote) = key_ref.strip_prefix("remote:") {
+        let (url, address) = match remote.rsplit_once('#') {
+            Some((url, address)) => (url, address.trim().parse()?),
+            None => bail!("remote signer should be remote:<url>#<address>"),
+        };
+        return Ok(Arc::new(RemoteSigner::new(url, address)?));
+    }
+
+    Ok(Arc::new(LocalSigner::new(&resolve_key(network, key_ref)?)?))
+}
+
+lazy_static! {
+    // next nonce of each sender, none until it is taken from the node
+    static ref NONCES: Mutex<HashMap<H160, Arc<tokio::sync::Mutex<Option<U256>>>>> =
+        Mutex::new(HashMap::new());
+}
+
+// nonce of the next transaction of `address`, counted here so concurrent tasks of a sender get
+// their own nonces, the count starts from the transactions of the node, the ones still in the
+// pool included
+async fn pending_nonce(web3: &Web3<AnyTransport>, address: H160) -> web3::Result<U256> {
+    next_nonce(
+        address,
+        web3.eth()
+            .transaction_count(address, Some(BlockNumber::Pending)),
+    )
+    .await
+}
+
+async fn next_nonce<F>(address: H160, seed: F) -> web3::Result<U256>
+where
+    F: Future<Output = web3::Result<U256>>,
+{
+    let counter = NONCES.lock().unwrap().entry(address).or_default().clone();
+    let mut counter = counter.lock().await;
+    let nonce = match *counter {
+        Some(nonce) => nonce,
+        None => seed.await?,
+    };
+    *counter = Some(nonce + 1);
+
+    Ok(nonce)
+}
+
+// a transaction of `address` which took a nonce but didn't get into the pool, or was refused
+// with nonce too low, leaves the count off, the next one takes it from the node again
+pub(crate) async fn resync_nonce(address: H160) {
+    let counter = NONCES.lock().unwrap().get(&address).cloned();
+    if let Some(counter) = counter {
+        *counter.lock().await = None;
+    }
+}
+
+pub(crate) struct LocalSigner {
+    secret_key: SecretKey,
+}
+
+impl LocalSigner {
+    pub(crate) fn new(sec_key: &str) -> anyhow::Result<Self> {
+        let secret_key =
+            SecretKey::from_str(sec_key).map_err(|e| anyhow!("secret key error: {}", e))?;
+
+        Ok(LocalSigner { secret_key })
+    }
+}
+
+#[async_trait]
+impl TxSigner for LocalSigner {
+    fn address(&self) -> H160 {
+        (&self.secret_key).address()
+    }
+
+    async fn sign_transaction(
+        &self,
+        web3: &Web3<AnyTransport>,
+        mut tx: TransactionParameters,
+    ) -> web3::Result<Bytes> {
+        let counted = tx.nonce.is_none();
+        if counted {
+            tx.nonce = Some(pending_nonce(web3, self.address()).await?);
+        }
+        let signed = web3.accounts().sign_transaction(tx, &self.secret_key).await;
+        if signed.is_err() && counted {
+            resync_nonce(self.address()).await;
+        }
+
+        Ok(signed?.raw_transaction)
+    }
+}
+
+// JSON-RPC signer service answering `eth_signTransaction`, like Clef or Web3Signer
+pub(crate) struct RemoteSigner {
+    transport: Http,
+    address: H160,
+}
+
+impl RemoteSigner {
+    pub(crate) fn new(url: &str, address: H160) -> anyhow::Result<Self> {
+        let transport = http(url)?;
+
+        Ok(RemoteSigner { transport, address })
+    }
+}
+
+#[async_trait]
+impl TxSigner for RemoteSigner {
+    fn address(&self) -> H160 {
+        self.address
+    }
+
+    async fn sign_transaction(
+        &self,
+        web3: &Web3<AnyTransport>,
+        tx: TransactionParameters,
+    ) -> web3::Result<Bytes> {
+        let eth = web3.eth();
+
+        // the signer only signs, fill what the local signing would take from the node
+        let chain_id = match tx.chain_id {
+            Some(chain_id) => chain_id,
+            None => eth.chain_id().await?.as_u64(),
+        };
+        let dynamic_fee = tx.transaction_type == Some(U64::from(2));
+        let gas_price = match (dynamic_fee, tx.gas_price, tx.max_fee_per_gas) {
+            (false, Some(gas_price), _) | (true, _, Some(gas_price)) => gas_price,
+            _ => eth.gas_price().await?,
+        };
+
+        let counted = tx.nonce.is_none();
+        let nonce = match tx.nonce {
+            Some(nonce) => nonce,
+            None => pending_nonce(web3, self.address).await?,
+        };
+
+        let mut req = Map::new();
+        req.insert("from".into(), json!(self.address));
+        if let Some(to) = tx.to {
+            req.insert("to".into(), json!(to));
+        }
+        req.insert("gas".into(), json!(tx.gas));
+        req.insert("value".into(), json!(tx.value));
+        req.insert("data".into(), json!(tx.data));
+        req.insert("nonce".into(), json!(nonce));
+        req.insert("chainId".into(), json!(U256::from(chain_id)));
+        if let Some(transaction_type) = tx.transaction_type {
+            req.insert("type".into(), json!(transaction_type));
+        }
+        if dynamic_fee {
+            req.insert("maxFeePerGas".into(), json!(gas_price));
+            // unset is 0, like the tip of the fees the tasks resolve
+            req.insert(
+                "maxPriorityFeePerGas".into(),
+                json!(tx.max_priority_fee_per_gas.unwrap_or_default()),
+            );
+        } else {
+            req.insert("gasPrice".into(), json!(gas_price));
+        }
+        if let Some(access_list) = tx.access_list {
+            req.insert("accessList".into(), json!(access_list));
+        }
+
+        let signed = CallFuture::new(
+            self.transport
+                .execute("eth_signTransaction", vec![Value::Object(req)]),
+        )
+        .await
+        .and_then(|res: Value| {
+            // Clef answers {"raw": "0x..", "tx": {..}}, Web3Signer answers the raw hex string
+            let raw = match res.get("raw") {
+                Some(raw) => raw.clone(),
+                None => res,
+            };
+            serde_json::from_value(raw)
+                .map_err(|e| web3::Error::Decoder(format!("eth_signTransaction result: {}", e)))
+        });
+        if signed.is_err() && counted {
+            resync_nonce(self.address).await;
+        }
+
+        signed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn nonce_counter() {
+        let address = H160::repeat_byte(7);
+        let seed = |nonce: u64| async move { Ok(U256::from(nonce)) };
+
+        // seeded once, the concurrent tasks of a sender count up from it
+        let nonces = futures::future::join_all((0..3).map(|_| next_nonce(address, seed(5)))).await;
+        let mut nonces: Vec<U256> = nonces.into_iter().map(Result::unwrap).collect();
+        nonces.sort();
+        assert_eq!(nonces, [5.into(), 6.into(), 7.into()]);
+
+        resync_nonce(address).await;
+        assert_eq!(next_nonce(address, seed(9)).await.unwrap(), 9.into());
+        assert_eq!(next_nonce(address, seed(0)).await.unwrap(), 10.into());
+    }
+}
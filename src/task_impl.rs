@@ -1,27 +1,148 @@
+use crate::{
+    commands::{GasValue, TxType},
+    retry::{is_already_sent, Retry},
+    signer::{resync_nonce, TxSigner},
+    transport::{connect, wait_receipt_on, AnyTransport},
+};
+use anyhow::anyhow;
 use secp256k1::SecretKey;
-use std::{fs, str::FromStr, time};
+use std::{fs, str::FromStr, time::Duration};
 use web3::{
     self,
-    api::{self, Namespace},
-    contract::{Contract, Options},
-    ethabi::Token,
+    api::{Eth, Namespace},
+    contract::{deploy, Contract, Options},
+    ethabi::{self, Token},
+    helpers::CallFuture,
+    signing::{keccak256, Key},
     types::H160,
-    types::{H256, U256},
+    types::{
+        AccessList, Address, BlockId, BlockNumber, Bytes, CallRequest, TransactionId,
+        TransactionParameters, TransactionReceipt, H256, U256, U64,
+    },
+    Transport, Web3,
 };
 
 const PULL_INTERVAL: u64 = 50;
+pub(crate) const DEPLOY_GAS: u64 = 1000000;
+pub(crate) const CALL_GAS: u64 = 100000;
+pub(crate) const TRANSFER_GAS: u64 = 21000;
+// applied to estimated gas when gas is "auto"
+pub(crate) const GAS_MULTIPLIER: f64 = 1.2;
+
+// transaction settings shared by deploy, call and transfer
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TxParams {
+    pub(crate) gas: Option<GasValue>,
+    pub(crate) gas_price: Option<GasValue>,
+    pub(crate) gas_multiplier: f64,
+    pub(crate) value: Option<U256>,
+    pub(crate) tx_type: TxType,
+    pub(crate) max_fee_per_gas: Option<GasValue>,
+    pub(crate) max_priority_fee_per_gas: Option<GasValue>,
+    pub(crate) access_list: Option<AccessList>,
+    pub(crate) chain_id: Option<u64>,
+}
+
+// fee fields of a transaction, "auto" values are already fetched from the node
+#[derive(Clone, Debug, Default)]
+struct Fees {
+    transaction_type: Option<U64>,
+    gas_price: Option<U256>,
+    max_fee_per_gas: Option<U256>,
+    max_priority_fee_per_gas: Option<U256>,
+    access_list: Option<AccessList>,
+}
+
+impl Fees {
+    fn options(&self, gas: Option<U256>, value: Option<U256>) -> Options {
+        Options {
+            gas,
+            gas_price: self.gas_price,
+            value,
+            transaction_type: self.transaction_type,
+            access_list: self.access_list.clone(),
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            ..Default::default()
+        }
+    }
+
+    fn tx_parameters(
+        &self,
+        gas: U256,
+        value: U256,
+        chain_id: Option<u64>,
+    ) -> TransactionParameters {
+        TransactionParameters {
+            gas,
+            gas_price: self.gas_price,
+            value,
+            chain_id,
+            transaction_type: self.transaction_type,
+            access_list: self.access_list.clone(),
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            ..Default::default()
+        }
+    }
+
+    fn call_request(&self, from: Address, to: Option<Address>, value: Option<U256>) -> CallRequest {
+        CallRequest {
+            from: Some(from),
+            to,
+            gas_price: self.gas_price,
+            value,
+            transaction_type: self.transaction_type,
+            access_list: self.access_list.clone(),
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            ..Default::default()
+        }
+    }
+}
 
 pub(crate) async fn contract_deploy(
     rpc_url: &str,
-    sec_key: &str,
+    signer: &dyn TxSigner,
     code_path: &str,
     abi_path: &str,
-    gas: u32,
-    gas_price: u32,
+    tx_params: TxParams,
     args: Vec<Token>,
-) -> web3::contract::Result<H160> {
-    let transport = web3::transports::Http::new(rpc_url)?;
-    let web3 = web3::Web3::new(transport);
+    retry: &Retry,
+) -> anyhow::Result<(H160, TransactionReceipt)> {
+    let (web3, raw_transaction) = retry
+        .run(|| sign_deploy(rpc_url, signer, code_path, abi_path, &tx_params, &args))
+        .await?;
+    let transaction_hash = send_signed(&web3, signer.address(), raw_transaction, retry).await?;
+    let receipt = retry
+        .run(|| {
+            wait_receipt_on(
+                &web3,
+                transaction_hash,
+                PULL_INTERVAL,
+                retry.receipt_timeout(),
+            )
+        })
+        .await?;
+
+    match (receipt.status, receipt.contract_address) {
+        (Some(status), _) if status == 0.into() => {
+            Err(deploy::Error::ContractDeploymentFailure(receipt.transaction_hash).into())
+        }
+        (_, Some(address)) => Ok((address, receipt)),
+        (_, None) => Err(deploy::Error::ContractDeploymentFailure(receipt.transaction_hash).into()),
+    }
+}
+
+async fn sign_deploy(
+    rpc_url: &str,
+    signer: &dyn TxSigner,
+    code_path: &str,
+    abi_path: &str,
+    tx_params: &TxParams,
+    args: &[Token],
+) -> anyhow::Result<(Web3<AnyTransport>, Bytes)> {
+    let web3 = connect(rpc_url).await?;
 
     // let _account: H160 = _account.parse().unwrap();
 
@@ -31,82 +152,99 @@ pub(crate) async fn contract_deploy(
     //     .await?;
     // println!("nonce: {}, nonce_add: {}", nonce, nonce_add);
 
-    let byetcode = fs::read(code_path).unwrap();
-    let abi = fs::read(abi_path).unwrap();
+    let byetcode = read_file(code_path)?;
+    let abi = read_file(abi_path)?;
 
-    let secretkey = SecretKey::from_str(sec_key).unwrap();
+    let data = deploy_data(&abi, &byetcode, args)?;
+    let fees = resolve_fees(&eth, tx_params).await?;
+    let gas = match tx_params.gas {
+        Some(GasValue::Fixed(gas)) => gas,
+        Some(GasValue::Auto) => {
+            let req = CallRequest {
+                data: Some(data.clone().into()),
+                ..fees.call_request(signer.address(), None, tx_params.value)
+            };
+            let estimated = eth.estimate_gas(req, None).await?;
+            scale_gas(estimated, tx_params.gas_multiplier)
+        }
+        None => DEPLOY_GAS.into(),
+    };
 
-    let contract;
-    if args.is_empty() {
-        contract = Contract::deploy(eth, &abi)?
-            .confirmations(1)
-            .poll_interval(time::Duration::from_millis(PULL_INTERVAL))
-            .options(Options::with(|opt| {
-                opt.gas = Some(gas.into());
-                opt.gas_price = Some(gas_price.into());
-                // opt.nonce = Some(nonce + nonce_add);
-            }))
-            .sign_with_key_and_execute(
-                std::str::from_utf8(&byetcode).unwrap(),
-                (),
-                &secretkey,
-                None,
-            )
-            .await?;
-    } else {
-        contract = Contract::deploy(eth, &abi)?
-            .confirmations(1)
-            .poll_interval(time::Duration::from_millis(PULL_INTERVAL))
-            .options(Options::with(|opt| {
-                opt.gas = Some(gas.into());
-                opt.gas_price = Some(gas_price.into());
-                // opt.nonce = Some(nonce + nonce_add);
-            }))
-            .sign_with_key_and_execute(
-                std::str::from_utf8(&byetcode).unwrap(),
-                args,
-                &secretkey,
-                None,
-            )
-            .await?;
-    }
+    // sign through the signer, the receipt is waited like `Contract::deploy` does
+    let tx = TransactionParameters {
+        data: data.into(),
+        ..fees.tx_parameters(gas, tx_params.value.unwrap_or_default(), tx_params.chain_id)
+    };
+    let raw_transaction = signer.sign_transaction(&web3, tx).await?;
 
-    Ok(contract.address())
+    Ok((web3, raw_transaction))
 }
 
 pub(crate) async fn contract_call(
     rpc_url: &str,
+    signer: &dyn TxSigner,
     contr_addr: &str,
-    sec_key: &str,
     // _account: &str,
     abi_path: &str,
-    gas: u32,
-    gas_price: u32,
+    tx_params: TxParams,
     func_name: &str,
     args: Vec<Token>,
-) -> web3::contract::Result<H256> {
-    let transport = web3::transports::Http::new(rpc_url)?;
-    let eth = api::Eth::new(transport);
-    let abi = fs::read(abi_path).unwrap();
-    let contr_addr: H160 = contr_addr.parse().unwrap();
+    retry: &Retry,
+) -> anyhow::Result<H256> {
+    let contr_addr = parse_address(contr_addr)?;
     // let _account: H160 = _account.parse().unwrap();
-    let contract = Contract::from_json(eth, contr_addr, &abi)?;
-    let secretkey = SecretKey::from_str(sec_key).unwrap();
 
-    let mut opt = Options::default();
-    opt.gas = Some(gas.into());
-    opt.gas_price = Some(gas_price.into());
+    let (web3, raw_transaction) = retry
+        .run(|| {
+            sign_call(
+                rpc_url, signer, contr_addr, abi_path, &tx_params, func_name, &args,
+            )
+        })
+        .await?;
 
-    let transaction_hash;
-    if args.is_empty() {
-        transaction_hash = contract.signed_call(func_name, (), opt, &secretkey).await?;
-    } else {
-        transaction_hash = contract
-            .signed_call(func_name, args, opt, &secretkey)
-            .await?;
-    }
+    Ok(send_signed(&web3, signer.address(), raw_transaction, retry).await?)
+}
 
-    Ok(transaction_hash)
+async fn sign_call(
+    rpc_url: &str,
+    signer: &dyn TxSigner,
+    contr_addr: H160,
+    abi_path: &str,
+    tx_params: &TxParams,
+    func_name: &str,
+    args: &[Token],
+) -> anyhow::Result<(Web3<AnyTransport>, Bytes)> {
+    let web3 = connect(rpc_url).await?;
+    let abi = read_file(abi_path)?;
+    let contract = Contract::from_json(web3.eth(), contr_addr, &abi)?;
+
+    let fees = resolve_fees(&web3.eth(), tx_params).await?;
+    let gas = match tx_params.gas {
+        Some(GasValue::Fixed(gas)) => gas,
+        Some(GasValue::Auto) => {
+            let from = signer.address();
+            let opt = fees.options(None, tx_params.value);
+            let estimated = contract
+                .estimate_gas(func_name, args.to_vec(), from, opt)
+                .await?;
+            scale_gas(estimated, tx_params.gas_multiplier)
+        }
+        None => CALL_GAS.into(),
+    };
+
+    // sign the call ourselves, `Contract::signed_call` can't take the chain id
+    let data = contract
+        .abi()
+        .function(func_name)
+        .and_then(|function| function.encode_input(args))?;
+    let tx = TransactionParameters {
+        to: Some(contr_addr),
+        data: data.into(),
+        ..fees.tx_parameters(gas, tx_params.value.unwrap_or_default(), tx_params.chain_id)
+    };
+    let raw_transaction = signer.sign_transaction(&web3, tx).await?;
+
+    Ok((web3, raw_transaction))
 }
 
 pub(crate) async fn contract_query(
@@ -116,11 +254,10 @@ pub(crate) async fn contract_query(
     abi_path: &str,
     func_name: &str,
     args: Vec<Token>,
-) -> web3::contract::Result<U256> {
-    let transport = web3::transports::Http::new(rpc_url)?;
-    let eth = api::Eth::new(transport);
-    let abi = fs::read(abi_path).unwrap();
-    let contr_addr: H160 = contr_addr.parse().unwrap();
+) -> anyhow::Result<U256> {
+    let eth = connect(rpc_url).await?.eth();
+    let abi = read_file(abi_path)?;
+    let contr_addr = parse_address(contr_addr)?;
     // let _account: H160 = _account.parse().unwrap();
     let contract = Contract::from_json(eth, contr_addr, &abi)?;
     // let _secretkey = SecretKey::from_str(_sec_key).unwrap();
@@ -136,12 +273,220 @@ pub(crate) async fn contract_query(
     Ok(result)
 }
 
-pub(crate) async fn get_balance(rpc_url: &str, account: &str) -> web3::Result<U256> {
-    let transport = web3::transports::Http::new(&rpc_url)?;
-    let web3 = web3::Web3::new(transport);
+pub(crate) async fn transfer(
+    rpc_url: &str,
+    signer: &dyn TxSigner,
+    to: &str,
+    tx_params: TxParams,
+    retry: &Retry,
+) -> anyhow::Result<H256> {
+    let to = parse_address(to)?;
+
+    let (web3, raw_transaction) = retry
+        .run(|| sign_transfer(rpc_url, signer, to, &tx_params))
+        .await?;
 
-    let account: H160 = account.parse().unwrap();
+    Ok(send_signed(&web3, signer.address(), raw_transaction, retry).await?)
+}
+
+async fn sign_transfer(
+    rpc_url: &str,
+    signer: &dyn TxSigner,
+    to: H160,
+    tx_params: &TxParams,
+) -> anyhow::Result<(Web3<AnyTransport>, Bytes)> {
+    let web3 = connect(rpc_url).await?;
+
+    let value = tx_params.value.unwrap_or_default();
+    let fees = resolve_fees(&web3.eth(), tx_params).await?;
+    let gas = match tx_params.gas {
+        Some(GasValue::Fixed(gas)) => gas,
+        Some(GasValue::Auto) => {
+            let req = fees.call_request(signer.address(), Some(to), Some(value));
+            let estimated = web3.eth().estimate_gas(req, None).await?;
+            scale_gas(estimated, tx_params.gas_multiplier)
+        }
+        None => TRANSFER_GAS.into(),
+    };
+
+    let tx = TransactionParameters {
+        to: Some(to),
+        ..fees.tx_parameters(gas, value, tx_params.chain_id)
+    };
+    let raw_transaction = signer.sign_transaction(&web3, tx).await?;
+
+    Ok((web3, raw_transaction))
+}
+
+// send a signed transaction of `from`, the same bytes are sent again on transient errors. A
+// resend answered with nonce too low or already known is looked up by its hash, to tell an
+// earlier send which went through from a nonce used by another transaction
+async fn send_signed(
+    web3: &Web3<AnyTransport>,
+    from: H160,
+    raw_transaction: Bytes,
+    retry: &Retry,
+) -> web3::Result<H256> {
+    let transaction_hash = H256(keccak256(&raw_transaction.0));
+
+    let mut attempt = 0;
+    let res = loop {
+        match web3
+            .eth()
+            .send_raw_transaction(raw_transaction.clone())
+            .await
+        {
+            Ok(hash) => break Ok(hash),
+            Err(e) if attempt > 0 && is_already_sent(&e) => {
+                let sent = retry
+                    .run(|| {
+                        web3.eth()
+                            .transaction(TransactionId::Hash(transaction_hash))
+                    })
+                    .await;
+                break match sent {
+                    Ok(Some(_)) => Ok(transaction_hash),
+                    Ok(None) => Err(e),
+                    Err(e) => Err(e),
+                };
+            }
+            Err(e) if retry.again(attempt, &e).await => attempt += 1,
+            Err(e) => break Err(e),
+        }
+    };
+    // the nonce of a transaction which isn't in the pool is counted again from the node
+    if res.is_err() {
+        resync_nonce(from).await;
+    }
+
+    res
+}
+
+// receipt of a sent transaction, waits until it is mined
+pub(crate) async fn wait_receipt(
+    rpc_url: &str,
+    transaction_hash: H256,
+    timeout: Duration,
+) -> web3::Result<TransactionReceipt> {
+    let web3 = connect(rpc_url).await?;
+
+    wait_receipt_on(&web3, transaction_hash, PULL_INTERVAL, timeout).await
+}
+
+pub(crate) async fn get_balance(rpc_url: &str, account: &str) -> anyhow::Result<U256> {
+    let web3 = connect(rpc_url).await?;
+
+    let account = parse_address(account)?;
     let balance = web3.eth().balance(account, None).await?;
 
     Ok(balance)
 }
+
+pub(crate) fn key_address(sec_key: &str) -> anyhow::Result<H160> {
+    let secretkey = SecretKey::from_str(sec_key).map_err(|e| anyhow!("secret key error: {}", e))?;
+
+    Ok((&secretkey).address())
+}
+
+// an address of the command line or a config, an error instead of a panic when it is not one
+fn parse_address(address: &str) -> anyhow::Result<H160> {
+    address
+        .trim()
+        .parse()
+        .map_err(|_| anyhow!("address format error: {}", address))
+}
+
+// abi and code files, a missing one is not an io error of the connection, so it is not retried
+fn read_file(path: &str) -> anyhow::Result<Vec<u8>> {
+    fs::read(path).map_err(|e| anyhow!("read {} failed: {}", path, e))
+}
+
+pub(crate) async fn get_chain_id(rpc_url: &str) -> web3::Result<u64> {
+    let web3 = connect(rpc_url).await?;
+
+    let chain_id = web3.eth().chain_id().await?;
+
+    Ok(chain_id.as_u64())
+}
+
+async fn resolve_gas_price<T: Transport>(
+    eth: &Eth<T>,
+    gas_price: &Option<GasValue>,
+) -> web3::Result<Option<U256>> {
+    match gas_price {
+        Some(GasValue::Fixed(gas_price)) => Ok(Some(*gas_price)),
+        Some(GasValue::Auto) => Ok(Some(eth.gas_price().await?)),
+        None => Ok(None),
+    }
+}
+
+async fn resolve_fees<T: Transport>(eth: &Eth<T>, tx_params: &TxParams) -> web3::Result<Fees> {
+    match tx_params.tx_type {
+        TxType::Legacy => Ok(Fees {
+            gas_price: resolve_gas_price(eth, &tx_params.gas_price).await?,
+            ..Default::default()
+        }),
+        TxType::AccessList => Ok(Fees {
+            transaction_type: Some(1.into()),
+            gas_price: resolve_gas_price(eth, &tx_params.gas_price).await?,
+            access_list: Some(tx_params.access_list.clone().unwrap_or_default()),
+            ..Default::default()
+        }),
+        TxType::DynamicFee => {
+            // web3 falls back to eth_gasPrice for an unset max fee, an unset tip is 0 like in the
+            // auto max fee, web3 and the remote signer would make it the whole max fee
+            let max_priority_fee_per_gas = match tx_params.max_priority_fee_per_gas {
+                Some(GasValue::Fixed(fee)) => fee,
+                Some(GasValue::Auto) => {
+                    CallFuture::new(eth.transport().execute("eth_maxPriorityFeePerGas", vec![]))
+                        .await?
+                }
+                None => U256::zero(),
+            };
+            let max_fee_per_gas = match tx_params.max_fee_per_gas {
+                Some(GasValue::Fixed(fee)) => Some(fee),
+                Some(GasValue::Auto) => {
+                    let block = eth.block(BlockId::Number(BlockNumber::Latest)).await?;
+                    match block.and_then(|b| b.base_fee_per_gas) {
+                        Some(base_fee) => Some(auto_max_fee(base_fee, max_priority_fee_per_gas)),
+                        None => Some(eth.gas_price().await?),
+                    }
+                }
+                None => None,
+            };
+
+            Ok(Fees {
+                transaction_type: Some(2.into()),
+                max_fee_per_gas,
+                max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
+                access_list: tx_params.access_list.clone(),
+                ..Default::default()
+            })
+        }
+    }
+}
+
+// "auto" max fee, room for the base fee to double on top of the tip
+pub(crate) fn auto_max_fee(base_fee: U256, max_priority_fee_per_gas: U256) -> U256 {
+    base_fee * 2 + max_priority_fee_per_gas
+}
+
+fn scale_gas(gas: U256, multiplier: f64) -> U256 {
+    gas * U256::from((multiplier * 1000.0) as u64) / 1000
+}
+
+// deploy transaction data, same as the one web3 builds for `Contract::deploy`
+fn deploy_data(abi: &[u8], bytecode: &[u8], args: &[Token]) -> anyhow::Result<Vec<u8>> {
+    let abi = ethabi::Contract::load(abi)?;
+    let code_hex = std::str::from_utf8(bytecode)
+        .map_err(|e| anyhow!("code is not text: {}", e))?
+        .trim()
+        .replace('"', "")
+        .replace("0x", "");
+    let code = hex::decode(&code_hex).map_err(|e| anyhow!("hex decode error: {}", e))?;
+
+    match abi.constructor() {
+        Some(constructor) => Ok(constructor.encode_input(code, args)?),
+        None => Ok(code),
+    }
+}
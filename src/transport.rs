@@ -0,0 +1,211 @@
+use crate::{
+    multi_tasks::add_confirm_ms,
+    ws::{self, WsOptions, WsTransport},
+};
+use anyhow::bail;
+use futures::StreamExt;
+use lazy_static::lazy_static;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Certificate, Client,
+};
+use std::{path::PathBuf, sync::RwLock, time};
+use tokio_native_tls::native_tls;
+use web3::{
+    error::TransportError,
+    transports::{Either, Http, Ipc},
+    types::{TransactionReceipt, H256},
+    Web3,
+};
+
+// transports with subscriptions
+pub(crate) type Duplex = Either<WsTransport, Ipc>;
+pub(crate) type AnyTransport = Either<Http, Duplex>;
+
+lazy_static! {
+    static ref OPTIONS: RwLock<Options> = RwLock::new(Options::default());
+}
+
+// headers, auth and TLS settings of the rpc connections, from the global command line flags
+#[derive(Debug, Default)]
+pub(crate) struct TransportOptions {
+    pub(crate) headers: Vec<String>,
+    pub(crate) basic_auth: Option<String>,
+    pub(crate) bearer_token: Option<String>,
+    pub(crate) ca_cert: Option<PathBuf>,
+    pub(crate) insecure: bool,
+}
+
+#[derive(Default)]
+struct Options {
+    // set when any option needs a custom http client
+    client: Option<Client>,
+    ws: WsOptions,
+}
+
+// build the http client shared by all connections, called once before any connect
+pub(crate) fn configure(options: TransportOptions) -> anyhow::Result<()> {
+    let mut headers = HeaderMap::new();
+    let mut ws = WsOptions {
+        insecure: options.insecure,
+        ..Default::default()
+    };
+    for header in &options.headers {
+        let (name, value) = match header.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => bail!("header {} should be \"Name: value\"", header),
+        };
+        headers.insert(HeaderName::from_bytes(name.as_bytes())?, value.parse()?);
+        ws.headers.push((name.to_string(), value.to_string()));
+    }
+
+    let basic_auth = match &options.basic_auth {
+        Some(basic_auth) => match basic_auth.split_once(':') {
+            Some((user, password)) => Some((user.to_string(), password.to_string())),
+            None => bail!("basic auth should be user:password"),
+        },
+        None => None,
+    };
+    let authorization = match (&basic_auth, &options.bearer_token) {
+        (Some(_), Some(_)) => bail!("basic auth and bearer token can't be used together"),
+        (Some((user, password)), None) => Some(format!(
+            "Basic {}",
+            base64::encode(format!("{}:{}", user, password))
+        )),
+        (None, Some(token)) => Some(format!("Bearer {}", token)),
+        (None, None) => None,
+    };
+    if let Some(authorization) = authorization {
+        let mut value: HeaderValue = authorization.parse()?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+        ws.headers
+            .push((AUTHORIZATION.as_str().to_string(), authorization));
+    }
+
+    let mut builder = Client::builder().default_headers(headers);
+    if let Some(ca_cert) = &options.ca_cert {
+        let pem = std::fs::read(ca_cert)?;
+        builder = builder.add_root_certificate(Certificate::from_pem(&pem)?);
+        ws.ca_cert = Some(native_tls::Certificate::from_pem(&pem)?);
+    }
+    if options.insecure {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    let custom = !options.headers.is_empty()
+        || basic_auth.is_some()
+        || options.bearer_token.is_some()
+        || options.ca_cert.is_some()
+        || options.insecure;
+    *OPTIONS.write().unwrap() = Options {
+        client: if custom { Some(builder.build()?) } else { None },
+        ws,
+    };
+
+    Ok(())
+}
+
+// transport picked from the rpc url: ws:// and wss:// WebSocket, http:// and https:// Http,
+// ipc:// or a plain path an IPC socket
+pub(crate) async fn connect(rpc_url: &str) -> web3::Result<Web3<AnyTransport>> {
+    let transport = if rpc_url.starts_with("ws://") || rpc_url.starts_with("wss://") {
+        let options = OPTIONS.read().unwrap().ws.clone();
+        Either::Right(Either::Left(ws::connect(rpc_url, &options).await?))
+    } else if rpc_url.starts_with("http://") || rpc_url.starts_with("https://") {
+        Either::Left(http(rpc_url)?)
+    } else {
+        let path = rpc_url.strip_prefix("ipc://").unwrap_or(rpc_url);
+        Either::Right(Either::Right(connect_ipc(path).await?))
+    };
+
+    Ok(Web3::new(transport))
+}
+
+// Http transport with the headers, auth and TLS settings of the command line
+pub(crate) fn http(rpc_url: &str) -> web3::Result<Http> {
+    let client = OPTIONS.read().unwrap().client.clone();
+    match client {
+        Some(client) => Ok(Http::with_client(client, rpc_url.parse()?)),
+        None => Http::new(rpc_url),
+    }
+}
+
+#[cfg(unix)]
+async fn connect_ipc(path: &str) -> web3::Result<Ipc> {
+    Ipc::new(path).await
+}
+
+#[cfg(not(unix))]
+async fn connect_ipc(path: &str) -> web3::Result<Ipc> {
+    Err(web3::Error::Transport(TransportError::Message(format!(
+        "IPC socket {} is only supported on unix",
+        path
+    ))))
+}
+
+// subscriptions of the transport, None over http
+pub(crate) fn duplex(web3: &Web3<AnyTransport>) -> Option<Web3<Duplex>> {
+    match web3.transport() {
+        Either::Left(_) => None,
+        Either::Right(duplex) => Some(Web3::new(duplex.clone())),
+    }
+}
+
+// receipt of a sent transaction, an error when it is not there within `timeout`
+pub(crate) async fn wait_receipt_on(
+    web3: &Web3<AnyTransport>,
+    transaction_hash: H256,
+    poll_interval: u64,
+    timeout: time::Duration,
+) -> web3::Result<TransactionReceipt> {
+    let beg = time::Instant::now();
+    let res = tokio::time::timeout(timeout, poll_receipt(web3, transaction_hash, poll_interval))
+        .await
+        .unwrap_or_else(|_| {
+            Err(web3::Error::InvalidResponse(format!(
+                "no receipt for {:?} within {}s",
+                transaction_hash,
+                timeout.as_secs_f64()
+            )))
+        });
+    // the wait is on the chain, it stays out of the task time
+    add_confirm_ms(beg.elapsed().as_millis() as u64);
+
+    res
+}
+
+// checked on every new head when the transport has subscriptions and polled every
+// `poll_interval` milliseconds otherwise
+async fn poll_receipt(
+    web3: &Web3<AnyTransport>,
+    transaction_hash: H256,
+    poll_interval: u64,
+) -> web3::Result<TransactionReceipt> {
+    if let Some(duplex) = duplex(web3) {
+        let mut heads = duplex.eth_subscribe().subscribe_new_heads().await?;
+        loop {
+            if let Some(receipt) = duplex.eth().transaction_receipt(transaction_hash).await? {
+                let _ = heads.unsubscribe().await;
+                return Ok(receipt);
+            }
+            match heads.next().await {
+                Some(head) => {
+                    head?;
+                }
+                None => {
+                    return Err(web3::Error::Transport(TransportError::Message(
+                        "new heads subscription closed".to_string(),
+                    )))
+                }
+            }
+        }
+    }
+
+    loop {
+        if let Some(receipt) = web3.eth().transaction_receipt(transaction_hash).await? {
+            return Ok(receipt);
+        }
+        tokio::time::sleep(time::Duration::from_millis(poll_interval)).await;
+    }
+}
@@ -0,0 +1,219 @@
+use crate::{
+    inspect::log_line,
+    transport::{connect, duplex},
+};
+use anyhow::bail;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, path::PathBuf, time};
+use web3::{
+    ethabi,
+    types::{BlockId, FilterBuilder, Log, H160, H256},
+    Transport, Web3,
+};
+
+// processed ranges kept to find the fork point of a reorg
+const REORG_DEPTH: usize = 64;
+// blocks asked in one `eth_getLogs`
+const MAX_RANGE: u64 = 1000;
+const MAX_BACKOFF_SECS: u64 = 30;
+
+pub(crate) struct WatchParams {
+    pub(crate) contract_addr: H160,
+    pub(crate) abi: ethabi::Contract,
+    pub(crate) event: ethabi::Event,
+    pub(crate) topics: Vec<Option<Vec<H256>>>,
+    // first block when there is no saved height, the current head by default
+    pub(crate) from_block: Option<u64>,
+    // file keeping the last processed block, to resume after a restart
+    pub(crate) state: Option<PathBuf>,
+    pub(crate) poll_interval: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct WatchState {
+    block: u64,
+    hash: H256,
+}
+
+// one processed block range, its last block hash and the logs printed for it
+struct Processed {
+    from: u64,
+    to: u64,
+    hash: H256,
+    logs: Vec<Log>,
+}
+
+struct Watcher {
+    params: WatchParams,
+    last: Option<u64>,
+    recent: VecDeque<Processed>,
+}
+
+// print decoded events as they arrive, reconnecting with backoff until interrupted
+pub(crate) async fn watch(rpc_url: &str, params: WatchParams) -> anyhow::Result<()> {
+    let mut watcher = Watcher::new(params).await?;
+
+    let mut backoff = 1;
+    loop {
+        let last = watcher.last;
+        if let Err(e) = watcher.follow(rpc_url).await {
+            // status goes to stderr, stdout only has the json lines
+            eprintln!("watch error: {}, reconnect in {}s", e, backoff);
+        }
+
+        if watcher.last != last {
+            backoff = 1;
+        }
+        tokio::time::sleep(time::Duration::from_secs(backoff)).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+    }
+}
+
+impl Watcher {
+    async fn new(params: WatchParams) -> anyhow::Result<Self> {
+        let mut watcher = Watcher {
+            params,
+            last: None,
+            recent: VecDeque::new(),
+        };
+
+        if let Some(state) = &watcher.params.state {
+            if state.exists() {
+                let state: WatchState = serde_json::from_slice(&tokio::fs::read(state).await?)?;
+                eprintln!("resume after block {}", state.block);
+                watcher.last = Some(state.block);
+                // a reorg while stopped is found like any other
+                watcher.recent.push_back(Processed {
+                    from: state.block,
+                    to: state.block,
+                    hash: state.hash,
+                    logs: Vec::new(),
+                });
+            }
+        }
+
+        Ok(watcher)
+    }
+
+    // new heads from `eth_subscribe` when the transport has subscriptions, polling otherwise,
+    // the logs of each head are fetched the same way so reorgs and resuming work for both
+    async fn follow(&mut self, rpc_url: &str) -> anyhow::Result<()> {
+        let web3 = connect(rpc_url).await?;
+
+        let head = web3.eth().block_number().await?;
+        self.advance(&web3, head.as_u64()).await?;
+
+        match duplex(&web3) {
+            Some(duplex) => {
+                let mut heads = duplex.eth_subscribe().subscribe_new_heads().await?;
+                while let Some(header) = heads.next().await {
+                    if let Some(number) = header?.number {
+                        self.advance(&web3, number.as_u64()).await?;
+                    }
+                }
+
+                bail!("subscription closed")
+            }
+            None => loop {
+                tokio::time::sleep(time::Duration::from_millis(self.params.poll_interval)).await;
+                let head = web3.eth().block_number().await?;
+                self.advance(&web3, head.as_u64()).await?;
+            },
+        }
+    }
+
+    async fn advance<T: Transport>(&mut self, web3: &Web3<T>, head: u64) -> anyhow::Result<()> {
+        self.check_reorg(web3).await?;
+
+        let mut from = match self.last {
+            Some(last) => last + 1,
+            None => self.params.from_block.unwrap_or(head),
+        };
+        while from <= head {
+            let to = head.min(from + MAX_RANGE - 1);
+
+            // hash first, a reorg between the two calls is found on the next check
+            let hash = match block_hash(web3, to).await? {
+                Some(hash) => hash,
+                None => bail!("block {} not found", to),
+            };
+            let filter = FilterBuilder::default()
+                .address(vec![self.params.contract_addr])
+                .from_block(from.into())
+                .to_block(to.into())
+                .topics(
+                    Some(vec![self.params.event.signature()]),
+                    self.params.topics[0].clone(),
+                    self.params.topics[1].clone(),
+                    self.params.topics[2].clone(),
+                )
+                .build();
+            let logs = web3.eth().logs(filter).await?;
+
+            for log in &logs {
+                println!("{}", log_line(&self.params.abi, log));
+            }
+
+            self.recent.push_back(Processed {
+                from,
+                to,
+                hash,
+                logs,
+            });
+            if self.recent.len() > REORG_DEPTH {
+                self.recent.pop_front();
+            }
+            self.last = Some(to);
+            self.save_state(to, hash).await?;
+
+            from = to + 1;
+        }
+
+        Ok(())
+    }
+
+    // drop the processed ranges no longer on the chain and print their events as removed
+    async fn check_reorg<T: Transport>(&mut self, web3: &Web3<T>) -> anyhow::Result<()> {
+        let mut reorged = false;
+        let mut removed = 0;
+        while let Some(processed) = self.recent.back() {
+            // a block missing after a reorg made the chain shorter counts as replaced
+            if block_hash(web3, processed.to).await? == Some(processed.hash) {
+                break;
+            }
+
+            reorged = true;
+            let processed = self.recent.pop_back().unwrap();
+            for mut log in processed.logs.into_iter().rev() {
+                log.removed = Some(true);
+                println!("{}", log_line(&self.params.abi, &log));
+                removed += 1;
+            }
+            self.last = processed.from.checked_sub(1);
+        }
+
+        if reorged {
+            eprintln!(
+                "reorg, {} events removed, continue after block {:?}",
+                removed, self.last
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn save_state(&self, block: u64, hash: H256) -> anyhow::Result<()> {
+        if let Some(state) = &self.params.state {
+            tokio::fs::write(state, serde_json::to_vec(&WatchState { block, hash })?).await?;
+        }
+
+        Ok(())
+    }
+}
+
+async fn block_hash<T: Transport>(web3: &Web3<T>, number: u64) -> web3::Result<Option<H256>> {
+    let block = web3.eth().block(BlockId::Number(number.into())).await?;
+
+    Ok(block.and_then(|b| b.hash))
+}
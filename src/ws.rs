@@ -0,0 +1,354 @@
+use futures::{
+    channel::{mpsc, oneshot},
+    future::BoxFuture,
+    FutureExt, StreamExt,
+};
+use jsonrpc_core as rpc;
+use reqwest::Url;
+use soketto::{
+    connection::{Receiver, Sender},
+    handshake::{client::Header, Client, ServerResponse},
+};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_native_tls::{native_tls, TlsConnector};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+use web3::{
+    api::SubscriptionId, error::TransportError, helpers, DuplexTransport, Error, RequestId,
+    Transport,
+};
+
+type Responses = web3::Result<Vec<web3::Result<rpc::Value>>>;
+
+trait Socket: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Socket for T {}
+
+type Connection = Compat<Box<dyn Socket>>;
+
+// handshake headers and TLS settings, the same the http client uses
+#[derive(Default, Clone)]
+pub(crate) struct WsOptions {
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) ca_cert: Option<native_tls::Certificate>,
+    pub(crate) insecure: bool,
+}
+
+enum Message {
+    Request {
+        id: RequestId,
+        request: String,
+        response: oneshot::Sender<Responses>,
+    },
+    Subscribe {
+        id: SubscriptionId,
+        sink: mpsc::UnboundedSender<rpc::Value>,
+    },
+    Unsubscribe {
+        id: SubscriptionId,
+    },
+}
+
+// WebSocket transport like the one of web3, which only takes basic auth from the url and the
+// system certificates
+#[derive(Debug, Clone)]
+pub(crate) struct WsTransport {
+    id: Arc<AtomicUsize>,
+    messages: mpsc::UnboundedSender<Message>,
+}
+
+fn transport_error(message: String) -> Error {
+    Error::Transport(TransportError::Message(message))
+}
+
+fn closed() -> Error {
+    transport_error("WebSocket connection closed".to_string())
+}
+
+pub(crate) async fn connect(rpc_url: &str, options: &WsOptions) -> web3::Result<WsTransport> {
+    let url: Url = rpc_url.parse()?;
+    let host = match url.host_str() {
+        Some(host) => host.to_string(),
+        None => return Err(transport_error(format!("no host in {}", rpc_url))),
+    };
+    let tls = url.scheme() == "wss";
+    let port = url.port().unwrap_or(if tls { 443 } else { 80 });
+
+    let tcp = TcpStream::connect((host.as_str(), port)).await?;
+    tcp.set_nodelay(true)?;
+    let socket: Box<dyn Socket> = if tls {
+        let mut builder = native_tls::TlsConnector::builder();
+        if let Some(ca_cert) = &options.ca_cert {
+            builder.add_root_certificate(ca_cert.clone());
+        }
+        builder.danger_accept_invalid_certs(options.insecure);
+        let connector = TlsConnector::from(
+            builder
+                .build()
+                .map_err(|e| transport_error(format!("TLS error: {}", e)))?,
+        );
+        Box::new(
+            connector
+                .connect(&host, tcp)
+                .await
+                .map_err(|e| transport_error(format!("TLS error: {}", e)))?,
+        )
+    } else {
+        Box::new(tcp)
+    };
+
+    // credentials in the url go in the handshake like web3 sends them
+    let mut headers = options.headers.clone();
+    if let Some(password) = url.password() {
+        headers.push((
+            "Authorization".to_string(),
+            format!(
+                "Basic {}",
+                base64::encode(format!("{}:{}", url.username(), password))
+            ),
+        ));
+    }
+    let headers: Vec<Header> = headers
+        .iter()
+        .map(|(name, value)| Header {
+            name,
+            value: value.as_bytes(),
+        })
+        .collect();
+    let resource = match url.query() {
+        Some(query) => format!("{}?{}", url.path(), query),
+        None => url.path().to_string(),
+    };
+
+    let mut client = Client::new(socket.compat(), &host, &resource);
+    client.set_headers(&headers);
+    let response = client
+        .handshake()
+        .await
+        .map_err(|e| transport_error(format!("WebSocket handshake error: {}", e)))?;
+    let (sender, receiver) = match response {
+        ServerResponse::Accepted { .. } => client.into_builder().finish(),
+        ServerResponse::Redirect { status_code, .. } | ServerResponse::Rejected { status_code } => {
+            return Err(Error::Transport(TransportError::Code(status_code)))
+        }
+    };
+
+    let (messages, rx) = mpsc::unbounded();
+    tokio::spawn(run(sender, receiver, rx));
+
+    Ok(WsTransport {
+        id: Arc::new(AtomicUsize::new(1)),
+        messages,
+    })
+}
+
+// sends the requests and hands out the responses and notifications until the connection closes
+async fn run(
+    mut sender: Sender<Connection>,
+    receiver: Receiver<Connection>,
+    messages: mpsc::UnboundedReceiver<Message>,
+) {
+    let mut pending: BTreeMap<RequestId, oneshot::Sender<Responses>> = BTreeMap::new();
+    let mut subscriptions: BTreeMap<SubscriptionId, mpsc::UnboundedSender<rpc::Value>> =
+        BTreeMap::new();
+
+    let incoming = futures::stream::unfold(receiver, |mut receiver| async move {
+        let mut data = Vec::new();
+        let res = receiver.receive_data(&mut data).await.map(|_| data);
+        Some((res, receiver))
+    })
+    .fuse();
+    let mut messages = messages.fuse();
+    futures::pin_mut!(incoming);
+
+    loop {
+        futures::select! {
+            message = messages.next() => match message {
+                Some(Message::Request { id, request, response }) => {
+                    pending.insert(id, response);
+                    let sent = sender.send_text(request).await;
+                    if sent.and(sender.flush().await).is_err() {
+                        // the dropped sender fails the request
+                        pending.remove(&id);
+                    }
+                }
+                Some(Message::Subscribe { id, sink }) => {
+                    subscriptions.insert(id, sink);
+                }
+                Some(Message::Unsubscribe { id }) => {
+                    subscriptions.remove(&id);
+                }
+                None => break,
+            },
+            data = incoming.next() => match data {
+                Some(Ok(data)) => handle_data(&data, &subscriptions, &mut pending),
+                _ => break,
+            },
+        }
+    }
+}
+
+fn handle_data(
+    data: &[u8],
+    subscriptions: &BTreeMap<SubscriptionId, mpsc::UnboundedSender<rpc::Value>>,
+    pending: &mut BTreeMap<RequestId, oneshot::Sender<Responses>>,
+) {
+    if let Ok(notification) = helpers::to_notification_from_slice(data) {
+        if let rpc::Params::Map(params) = notification.params {
+            if let (Some(rpc::Value::String(id)), Some(result)) =
+                (params.get("subscription"), params.get("result"))
+            {
+                if let Some(sink) = subscriptions.get(&id.clone().into()) {
+                    let _ = sink.unbounded_send(result.clone());
+                }
+            }
+        }
+        return;
+    }
+
+    let outputs = match helpers::to_response_from_slice(data) {
+        Ok(rpc::Response::Single(output)) => vec![output],
+        Ok(rpc::Response::Batch(outputs)) => outputs,
+        Err(_) => return,
+    };
+    let id = match outputs.first() {
+        Some(rpc::Output::Success(success)) => success.id.clone(),
+        Some(rpc::Output::Failure(failure)) => failure.id.clone(),
+        None => return,
+    };
+    if let rpc::Id::Num(id) = id {
+        if let Some(response) = pending.remove(&(id as usize)) {
+            let _ = response.send(helpers::to_results_from_outputs(outputs));
+        }
+    }
+}
+
+impl Transport for WsTransport {
+    type Out = BoxFuture<'static, web3::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.id.fetch_add(1, Ordering::AcqRel);
+
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let (response, rx) = oneshot::channel();
+        let sent = self.messages.unbounded_send(Message::Request {
+            id,
+            request: helpers::to_string(&rpc::Request::Single(request)),
+            response,
+        });
+
+        async move {
+            sent.map_err(|_| closed())?;
+            match rx.await.map_err(|_| closed())??.into_iter().next() {
+                Some(res) => res,
+                None => Err(Error::InvalidResponse("empty batch response".to_string())),
+            }
+        }
+        .boxed()
+    }
+}
+
+impl DuplexTransport for WsTransport {
+    type NotificationStream = mpsc::UnboundedReceiver<rpc::Value>;
+
+    fn subscribe(&self, id: SubscriptionId) -> web3::Result<Self::NotificationStream> {
+        let (sink, stream) = mpsc::unbounded();
+        self.messages
+            .unbounded_send(Message::Subscribe { id, sink })
+            .map_err(|_| closed())?;
+
+        Ok(stream)
+    }
+
+    fn unsubscribe(&self, id: SubscriptionId) -> web3::Result<()> {
+        self.messages
+            .unbounded_send(Message::Unsubscribe { id })
+            .map_err(|_| closed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    // a server which reads one handshake request and turns it down with `status`
+    async fn refusing_server(status: &'static str) -> (u16, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = socket.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+            socket.write_all(response.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        (port, server)
+    }
+
+    #[tokio::test]
+    async fn handshake_headers() {
+        let (port, server) = refusing_server("403 Forbidden").await;
+        let options = WsOptions {
+            headers: vec![
+                ("X-Api-Key".to_string(), "secret".to_string()),
+                ("Authorization".to_string(), "Bearer token".to_string()),
+            ],
+            ..Default::default()
+        };
+        let res = connect(&format!("ws://127.0.0.1:{}/rpc?v=1", port), &options).await;
+        assert!(matches!(
+            res,
+            Err(Error::Transport(TransportError::Code(403)))
+        ));
+
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("GET /rpc?v=1 HTTP/1.1\r\n"),
+            "{}",
+            request
+        );
+        assert!(request.contains("X-Api-Key: secret\r\n"), "{}", request);
+        assert!(
+            request.contains("Authorization: Bearer token\r\n"),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn url_credentials() {
+        let (port, server) = refusing_server("401 Unauthorized").await;
+        let url = format!("ws://user:pass@127.0.0.1:{}/", port);
+        assert!(connect(&url, &WsOptions::default()).await.is_err());
+
+        let request = server.await.unwrap();
+        let basic = format!("Authorization: Basic {}\r\n", base64::encode("user:pass"));
+        assert!(request.contains(&basic), "{}", request);
+    }
+}